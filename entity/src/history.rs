@@ -3,6 +3,8 @@
 use sea_orm::entity::prelude::*;
 use serde::{Serialize, Deserialize};
 
+pub use crate::status::HistoryStatus;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "history")]
 pub struct Model {
@@ -17,6 +19,8 @@ pub struct Model {
     pub signature: String,
     pub price: i32,
     pub success: bool,
+    pub status: HistoryStatus,
+    pub metadata_uri: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
@@ -6,4 +6,16 @@ pub mod accounts;
 pub mod payments;
 pub mod seaql_migrations;
 pub mod tasks;
-pub mod history;
\ No newline at end of file
+pub mod history;
+pub mod idempotency_keys;
+pub mod integrations;
+pub mod jobs;
+pub mod nonces;
+pub mod rate_limits;
+pub mod refunds;
+pub mod refresh_tokens;
+pub mod revoked_tokens;
+pub mod status;
+pub mod status_transitions;
+pub mod watcher_cursors;
+pub mod watcher_failures;
\ No newline at end of file
@@ -3,6 +3,8 @@
 use sea_orm::entity::prelude::*;
 use serde::{Serialize, Deserialize};
 
+pub use crate::status::PaymentStatus;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "payments")]
 pub struct Model {
@@ -15,6 +17,18 @@ pub struct Model {
     pub success: bool,
     pub task_id: i32,
     pub tx: String,
+    #[sea_orm(column_type = "DateTime", nullable)]
+    pub confirmed_at: Option<DateTime>,
+    #[sea_orm(nullable)]
+    pub failure_reason: Option<String>,
+    pub currency: String,
+    pub decimals: i32,
+    pub base_amount: i64,
+    #[sea_orm(nullable)]
+    pub reference: Option<String>,
+    pub status: PaymentStatus,
+    #[sea_orm(column_type = "DateTime", nullable)]
+    pub quote_expires_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
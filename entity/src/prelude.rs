@@ -5,3 +5,14 @@ pub use super::payments::Entity as Payments;
 pub use super::seaql_migrations::Entity as SeaqlMigrations;
 pub use super::tasks::Entity as Tasks;
 pub use super::history::Entity as History;
+pub use super::idempotency_keys::Entity as IdempotencyKeys;
+pub use super::integrations::Entity as Integrations;
+pub use super::jobs::Entity as Jobs;
+pub use super::nonces::Entity as Nonces;
+pub use super::rate_limits::Entity as RateLimits;
+pub use super::refunds::Entity as Refunds;
+pub use super::refresh_tokens::Entity as RefreshTokens;
+pub use super::revoked_tokens::Entity as RevokedTokens;
+pub use super::status_transitions::Entity as StatusTransitions;
+pub use super::watcher_cursors::Entity as WatcherCursors;
+pub use super::watcher_failures::Entity as WatcherFailures;
\ No newline at end of file
@@ -0,0 +1,103 @@
+use sea_orm::entity::prelude::*;
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(24))")]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    #[sea_orm(string_value = "awaiting_payment")]
+    AwaitingPayment,
+    #[sea_orm(string_value = "paid")]
+    Paid,
+    #[sea_orm(string_value = "processing")]
+    Processing,
+    #[sea_orm(string_value = "applied")]
+    Applied,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+    #[sea_orm(string_value = "refunded")]
+    Refunded,
+    #[sea_orm(string_value = "expired")]
+    Expired,
+}
+
+impl TaskStatus {
+    pub fn can_transition_to(self, next: TaskStatus) -> bool {
+        use TaskStatus::*;
+
+        matches!(
+            (self, next),
+            (AwaitingPayment, Paid)
+                | (AwaitingPayment, Expired)
+                | (Expired, AwaitingPayment)
+                | (Paid, Processing)
+                | (Processing, Applied)
+                | (Processing, Failed)
+                | (Failed, Refunded)
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(24))")]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "paid")]
+    Paid,
+    #[sea_orm(string_value = "processing")]
+    Processing,
+    #[sea_orm(string_value = "applied")]
+    Applied,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+    #[sea_orm(string_value = "refunded")]
+    Refunded,
+    #[sea_orm(string_value = "expired")]
+    Expired,
+}
+
+impl PaymentStatus {
+    pub fn can_transition_to(self, next: PaymentStatus) -> bool {
+        use PaymentStatus::*;
+
+        matches!(
+            (self, next),
+            (Pending, Paid)
+                | (Pending, Expired)
+                | (Paid, Processing)
+                | (Paid, Failed)
+                | (Processing, Applied)
+                | (Processing, Failed)
+                | (Failed, Refunded)
+                | (Expired, Refunded)
+        )
+    }
+
+    /// Whether the payment has been confirmed on chain. Expired payments
+    /// landed after their quote ran out, so they were received too.
+    pub fn is_paid(self) -> bool {
+        !matches!(self, PaymentStatus::Pending)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(24))")]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryStatus {
+    #[sea_orm(string_value = "processing")]
+    Processing,
+    #[sea_orm(string_value = "applied")]
+    Applied,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+}
+
+impl HistoryStatus {
+    pub fn can_transition_to(self, next: HistoryStatus) -> bool {
+        use HistoryStatus::*;
+
+        matches!((self, next), (Processing, Applied) | (Processing, Failed))
+    }
+}
@@ -3,6 +3,8 @@
 use sea_orm::entity::prelude::*;
 use serde::{Serialize, Deserialize};
 
+pub use crate::status::TaskStatus;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "tasks")]
 pub struct Model {
@@ -14,6 +16,14 @@ pub struct Model {
     pub mint_address: String,
     pub price: i32,
     pub success: bool,
+    pub currency: String,
+    pub decimals: i32,
+    pub base_amount: i64,
+    #[sea_orm(column_type = "DateTime", nullable)]
+    pub quote_expires_at: Option<DateTime>,
+    pub status: TaskStatus,
+    pub rank: Option<String>,
+    pub collection: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
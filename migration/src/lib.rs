@@ -1,12 +1,54 @@
 pub use sea_schema::migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20220720_000002_create_nonces_table;
+mod m20220722_000003_create_token_tables;
+mod m20220726_000004_create_integrations_table;
+mod m20220729_000005_create_rate_limits_table;
+mod m20220802_000006_add_payment_verification;
+mod m20220804_000007_add_signature_indexes;
+mod m20220806_000008_add_payment_currency;
+mod m20220808_000009_add_payment_reference;
+mod m20220810_000010_create_watcher_cursors_table;
+mod m20220812_000011_create_refunds_table;
+mod m20220815_000012_add_task_quote_expiry;
+mod m20220818_000013_add_status_columns;
+mod m20220820_000014_create_idempotency_keys_table;
+mod m20220822_000015_add_task_rank_collection;
+mod m20220824_000016_add_history_metadata_uri;
+mod m20220828_000017_create_jobs_table;
+mod m20220830_000018_create_watcher_failures_table;
+mod m20220901_000019_add_refund_block_height;
+mod m20220903_000020_add_payment_quote_expiry;
+mod m20220905_000021_hash_refresh_tokens;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20220720_000002_create_nonces_table::Migration),
+            Box::new(m20220722_000003_create_token_tables::Migration),
+            Box::new(m20220726_000004_create_integrations_table::Migration),
+            Box::new(m20220729_000005_create_rate_limits_table::Migration),
+            Box::new(m20220802_000006_add_payment_verification::Migration),
+            Box::new(m20220804_000007_add_signature_indexes::Migration),
+            Box::new(m20220806_000008_add_payment_currency::Migration),
+            Box::new(m20220808_000009_add_payment_reference::Migration),
+            Box::new(m20220810_000010_create_watcher_cursors_table::Migration),
+            Box::new(m20220812_000011_create_refunds_table::Migration),
+            Box::new(m20220815_000012_add_task_quote_expiry::Migration),
+            Box::new(m20220818_000013_add_status_columns::Migration),
+            Box::new(m20220820_000014_create_idempotency_keys_table::Migration),
+            Box::new(m20220822_000015_add_task_rank_collection::Migration),
+            Box::new(m20220824_000016_add_history_metadata_uri::Migration),
+            Box::new(m20220828_000017_create_jobs_table::Migration),
+            Box::new(m20220830_000018_create_watcher_failures_table::Migration),
+            Box::new(m20220901_000019_add_refund_block_height::Migration),
+            Box::new(m20220903_000020_add_payment_quote_expiry::Migration),
+            Box::new(m20220905_000021_hash_refresh_tokens::Migration),
+        ]
     }
 }
@@ -0,0 +1,44 @@
+use entity::nonces;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220720_000002_create_nonces_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+            .table(nonces::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(nonces::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(nonces::Column::Pubkey).string().not_null())
+            .col(ColumnDef::new(nonces::Column::Nonce).string().not_null().unique_key())
+            .col(ColumnDef::new(nonces::Column::CreatedAt).date_time().not_null())
+            .col(ColumnDef::new(nonces::Column::ExpiresAt).date_time().not_null())
+            .col(ColumnDef::new(nonces::Column::ConsumedAt).date_time().null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(nonces::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
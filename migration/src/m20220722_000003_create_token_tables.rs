@@ -0,0 +1,70 @@
+use entity::{refresh_tokens, revoked_tokens};
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220722_000003_create_token_tables"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+            .table(refresh_tokens::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(refresh_tokens::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(refresh_tokens::Column::Pubkey).string().not_null())
+            .col(ColumnDef::new(refresh_tokens::Column::Token).string().not_null().unique_key())
+            .col(ColumnDef::new(refresh_tokens::Column::CreatedAt).date_time().not_null())
+            .col(ColumnDef::new(refresh_tokens::Column::ExpiresAt).date_time().not_null())
+            .col(ColumnDef::new(refresh_tokens::Column::RevokedAt).date_time().null())
+            .to_owned()
+        )
+        .await?;
+
+        manager.create_table(
+            Table::create()
+            .table(revoked_tokens::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(revoked_tokens::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(revoked_tokens::Column::Jti).string().not_null().unique_key())
+            .col(ColumnDef::new(revoked_tokens::Column::Pubkey).string().not_null())
+            .col(ColumnDef::new(revoked_tokens::Column::ExpiresAt).date_time().not_null())
+            .col(ColumnDef::new(revoked_tokens::Column::RevokedAt).date_time().not_null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(refresh_tokens::Entity)
+            .to_owned()
+        )
+        .await?;
+
+        manager.drop_table(
+            Table::drop()
+            .table(revoked_tokens::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
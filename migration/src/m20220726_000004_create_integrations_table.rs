@@ -0,0 +1,43 @@
+use entity::integrations;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220726_000004_create_integrations_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+            .table(integrations::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(integrations::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(integrations::Column::Name).string().not_null().unique_key())
+            .col(ColumnDef::new(integrations::Column::Secret).string().not_null())
+            .col(ColumnDef::new(integrations::Column::Active).boolean().not_null())
+            .col(ColumnDef::new(integrations::Column::CreatedAt).date_time().not_null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(integrations::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
@@ -0,0 +1,42 @@
+use entity::rate_limits;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220729_000005_create_rate_limits_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+            .table(rate_limits::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(rate_limits::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(rate_limits::Column::Key).string().not_null().unique_key())
+            .col(ColumnDef::new(rate_limits::Column::WindowStart).big_integer().not_null())
+            .col(ColumnDef::new(rate_limits::Column::Count).integer().not_null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(rate_limits::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
@@ -0,0 +1,49 @@
+use entity::payments;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220802_000006_add_payment_verification"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .add_column(ColumnDef::new(payments::Column::ConfirmedAt).date_time().null())
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .add_column(ColumnDef::new(payments::Column::FailureReason).string().null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .drop_column(payments::Column::ConfirmedAt)
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .drop_column(payments::Column::FailureReason)
+            .to_owned()
+        )
+        .await
+    }
+}
@@ -0,0 +1,69 @@
+use entity::history;
+use sea_schema::migration::prelude::*;
+use sea_orm::{ConnectionTrait, Statement};
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220804_000007_add_signature_indexes"
+    }
+}
+
+/// Flags signatures that were claimed more than once before the indexes
+/// existed. The oldest claim keeps the signature; later ones get the row id
+/// appended so they no longer match it.
+const FLAG_DUPLICATES: [&str; 2] = [
+    "UPDATE \"history\" SET \"signature\" = \"signature\" || ':duplicate:' || \"id\" WHERE \"id\" NOT IN (SELECT MIN(\"id\") FROM \"history\" GROUP BY \"signature\")",
+    "UPDATE \"payments\" SET \"tx\" = \"tx\" || ':duplicate:' || \"id\" WHERE \"tx\" <> 'none' AND \"id\" NOT IN (SELECT MIN(\"id\") FROM \"payments\" WHERE \"tx\" <> 'none' GROUP BY \"tx\")",
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        for sql in FLAG_DUPLICATES {
+            db.execute(Statement::from_string(db.get_database_backend(), sql.to_owned()))
+                .await?;
+        }
+
+        manager.create_index(
+            Index::create()
+            .name("idx-history-signature")
+            .table(history::Entity)
+            .col(history::Column::Signature)
+            .unique()
+            .to_owned()
+        )
+        .await?;
+
+        // -- Unpaid payments all carry the "none" placeholder, so only real
+        // -- signatures take part in the unique index
+        db.execute(Statement::from_string(
+            db.get_database_backend(),
+            "CREATE UNIQUE INDEX IF NOT EXISTS \"idx-payments-tx\" ON \"payments\" (\"tx\") WHERE \"tx\" <> 'none'".to_owned(),
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(
+            Index::drop()
+            .name("idx-payments-tx")
+            .table(entity::payments::Entity)
+            .to_owned()
+        )
+        .await?;
+
+        manager.drop_index(
+            Index::drop()
+            .name("idx-history-signature")
+            .table(history::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
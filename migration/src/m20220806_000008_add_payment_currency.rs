@@ -0,0 +1,103 @@
+use entity::{payments, tasks};
+use sea_schema::migration::prelude::*;
+use sea_orm::{ConnectionTrait, Statement};
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220806_000008_add_payment_currency"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .add_column(ColumnDef::new(tasks::Column::Currency).string().not_null().default("SOL"))
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .add_column(ColumnDef::new(tasks::Column::Decimals).integer().not_null().default(9))
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .add_column(ColumnDef::new(tasks::Column::BaseAmount).big_integer().not_null().default(0))
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .add_column(ColumnDef::new(payments::Column::Currency).string().not_null().default("SOL"))
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .add_column(ColumnDef::new(payments::Column::Decimals).integer().not_null().default(9))
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .add_column(ColumnDef::new(payments::Column::BaseAmount).big_integer().not_null().default(0))
+            .to_owned()
+        )
+        .await?;
+
+        // -- Existing rows were priced in SOL at 0.01 SOL per price unit
+        let db = manager.get_connection();
+        db.execute(Statement::from_string(
+            db.get_database_backend(),
+            "UPDATE \"tasks\" SET \"base_amount\" = \"price\" * 10000000".to_owned(),
+        ))
+        .await?;
+        db.execute(Statement::from_string(
+            db.get_database_backend(),
+            "UPDATE \"payments\" SET \"base_amount\" = \"amount\" * 10000000".to_owned(),
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [tasks::Column::Currency, tasks::Column::Decimals, tasks::Column::BaseAmount] {
+            manager.alter_table(
+                Table::alter()
+                .table(tasks::Entity)
+                .drop_column(column)
+                .to_owned()
+            )
+            .await?;
+        }
+
+        for column in [payments::Column::Currency, payments::Column::Decimals, payments::Column::BaseAmount] {
+            manager.alter_table(
+                Table::alter()
+                .table(payments::Entity)
+                .drop_column(column)
+                .to_owned()
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+}
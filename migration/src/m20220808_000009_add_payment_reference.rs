@@ -0,0 +1,51 @@
+use entity::payments;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220808_000009_add_payment_reference"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .add_column(ColumnDef::new(payments::Column::Reference).string().null())
+            .to_owned()
+        )
+        .await?;
+
+        manager.create_index(
+            Index::create()
+            .name("idx-payments-reference")
+            .table(payments::Entity)
+            .col(payments::Column::Reference)
+            .unique()
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(
+            Index::drop()
+            .name("idx-payments-reference")
+            .table(payments::Entity)
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .drop_column(payments::Column::Reference)
+            .to_owned()
+        )
+        .await
+    }
+}
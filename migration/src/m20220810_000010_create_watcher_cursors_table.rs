@@ -0,0 +1,42 @@
+use entity::watcher_cursors;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220810_000010_create_watcher_cursors_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+            .table(watcher_cursors::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(watcher_cursors::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(watcher_cursors::Column::Name).string().not_null().unique_key())
+            .col(ColumnDef::new(watcher_cursors::Column::Signature).string().not_null())
+            .col(ColumnDef::new(watcher_cursors::Column::UpdatedAt).date_time().not_null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(watcher_cursors::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
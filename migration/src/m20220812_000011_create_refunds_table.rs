@@ -0,0 +1,52 @@
+use entity::refunds;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220812_000011_create_refunds_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+            .table(refunds::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(refunds::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(refunds::Column::PaymentId).integer().not_null().unique_key())
+            .col(ColumnDef::new(refunds::Column::Account).string().not_null())
+            .col(ColumnDef::new(refunds::Column::Currency).string().not_null())
+            .col(ColumnDef::new(refunds::Column::Decimals).integer().not_null())
+            .col(ColumnDef::new(refunds::Column::BaseAmount).big_integer().not_null())
+            .col(ColumnDef::new(refunds::Column::Reason).string().not_null())
+            .col(ColumnDef::new(refunds::Column::Status).string().not_null())
+            .col(ColumnDef::new(refunds::Column::Signature).string().null())
+            .col(ColumnDef::new(refunds::Column::ApprovedBy).string().null())
+            .col(ColumnDef::new(refunds::Column::FailureReason).string().null())
+            .col(ColumnDef::new(refunds::Column::CreatedAt).date_time().not_null())
+            .col(ColumnDef::new(refunds::Column::ApprovedAt).date_time().null())
+            .col(ColumnDef::new(refunds::Column::SentAt).date_time().null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(refunds::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
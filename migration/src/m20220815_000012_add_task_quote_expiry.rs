@@ -0,0 +1,35 @@
+use entity::tasks;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220815_000012_add_task_quote_expiry"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // -- Tasks created before quotes expired are left without one and
+        // -- have to be requoted before they can be paid
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .add_column(ColumnDef::new(tasks::Column::QuoteExpiresAt).date_time().null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .drop_column(tasks::Column::QuoteExpiresAt)
+            .to_owned()
+        )
+        .await
+    }
+}
@@ -0,0 +1,130 @@
+use entity::{history, payments, status_transitions, tasks};
+use sea_schema::migration::prelude::*;
+use sea_orm::{ConnectionTrait, Statement};
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220818_000013_add_status_columns"
+    }
+}
+
+/// Derives statuses for rows written while only the boolean flags existed.
+/// History rows decide the outcome, since payments were never flagged as
+/// successful before on-chain verification. A history row's `success` is the
+/// rank roll, so only rows that opened a refund failed to apply.
+const BACKFILL: [&str; 11] = [
+    "UPDATE \"history\" SET \"status\" = CASE WHEN \"payment_id\" IN (SELECT \"payment_id\" FROM \"refunds\") THEN 'failed' ELSE 'applied' END",
+    "UPDATE \"payments\" SET \"status\" = 'paid' WHERE \"success\"",
+    "UPDATE \"payments\" SET \"status\" = 'expired' WHERE NOT \"success\" AND \"tx\" <> 'none'",
+    "UPDATE \"payments\" SET \"status\" = 'applied' WHERE \"id\" IN (SELECT \"payment_id\" FROM \"history\" WHERE \"status\" = 'applied')",
+    "UPDATE \"payments\" SET \"status\" = 'failed' WHERE \"id\" IN (SELECT \"payment_id\" FROM \"history\" WHERE \"status\" = 'failed')",
+    "UPDATE \"payments\" SET \"status\" = 'refunded' WHERE \"id\" IN (SELECT \"payment_id\" FROM \"refunds\" WHERE \"status\" = 'sent')",
+    "UPDATE \"payments\" SET \"success\" = \"status\" <> 'pending'",
+    "UPDATE \"tasks\" SET \"status\" = 'expired' WHERE \"quote_expires_at\" IS NULL",
+    "UPDATE \"tasks\" SET \"status\" = 'failed' WHERE \"id\" IN (SELECT \"task_id\" FROM \"payments\" WHERE \"status\" = 'failed')",
+    "UPDATE \"tasks\" SET \"status\" = 'refunded' WHERE \"id\" IN (SELECT \"task_id\" FROM \"history\" WHERE \"status\" = 'failed' AND \"payment_id\" IN (SELECT \"id\" FROM \"payments\" WHERE \"status\" = 'refunded'))",
+    "UPDATE \"tasks\" SET \"status\" = 'applied' WHERE \"id\" IN (SELECT \"task_id\" FROM \"payments\" WHERE \"status\" = 'applied')",
+    "UPDATE \"tasks\" SET \"success\" = \"status\" = 'applied'",
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .add_column(ColumnDef::new(tasks::Column::Status).string().not_null().default("awaiting_payment"))
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .add_column(ColumnDef::new(payments::Column::Status).string().not_null().default("pending"))
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(history::Entity)
+            .add_column(ColumnDef::new(history::Column::Status).string().not_null().default("processing"))
+            .to_owned()
+        )
+        .await?;
+
+        manager.create_table(
+            Table::create()
+            .table(status_transitions::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(status_transitions::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(status_transitions::Column::Kind).string().not_null())
+            .col(ColumnDef::new(status_transitions::Column::RecordId).integer().not_null())
+            .col(ColumnDef::new(status_transitions::Column::FromStatus).string().null())
+            .col(ColumnDef::new(status_transitions::Column::ToStatus).string().not_null())
+            .col(ColumnDef::new(status_transitions::Column::CreatedAt).date_time().not_null())
+            .to_owned()
+        )
+        .await?;
+
+        manager.create_index(
+            Index::create()
+            .name("idx-status-transitions-record")
+            .table(status_transitions::Entity)
+            .col(status_transitions::Column::Kind)
+            .col(status_transitions::Column::RecordId)
+            .to_owned()
+        )
+        .await?;
+
+        let db = manager.get_connection();
+        for sql in BACKFILL {
+            db.execute(Statement::from_string(db.get_database_backend(), sql.to_owned()))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(status_transitions::Entity)
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(history::Entity)
+            .drop_column(history::Column::Status)
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .drop_column(payments::Column::Status)
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .drop_column(tasks::Column::Status)
+            .to_owned()
+        )
+        .await
+    }
+}
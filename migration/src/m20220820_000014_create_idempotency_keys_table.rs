@@ -0,0 +1,60 @@
+use entity::idempotency_keys;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220820_000014_create_idempotency_keys_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+            .table(idempotency_keys::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(idempotency_keys::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(idempotency_keys::Column::Key).string().not_null())
+            .col(ColumnDef::new(idempotency_keys::Column::Scope).string().not_null())
+            .col(ColumnDef::new(idempotency_keys::Column::Account).string().not_null())
+            .col(ColumnDef::new(idempotency_keys::Column::RequestHash).string().not_null())
+            .col(ColumnDef::new(idempotency_keys::Column::StatusCode).integer().null())
+            .col(ColumnDef::new(idempotency_keys::Column::Response).text().null())
+            .col(ColumnDef::new(idempotency_keys::Column::CreatedAt).date_time().not_null())
+            .col(ColumnDef::new(idempotency_keys::Column::ExpiresAt).date_time().not_null())
+            .to_owned()
+        )
+        .await?;
+
+        // -- Concurrent retries race on this index, so only one of them runs
+        manager.create_index(
+            Index::create()
+            .name("idx-idempotency-keys-key")
+            .table(idempotency_keys::Entity)
+            .col(idempotency_keys::Column::Scope)
+            .col(idempotency_keys::Column::Account)
+            .col(idempotency_keys::Column::Key)
+            .unique()
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(idempotency_keys::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
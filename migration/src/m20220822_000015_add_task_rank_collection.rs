@@ -0,0 +1,50 @@
+use entity::tasks;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220822_000015_add_task_rank_collection"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // -- Older tasks never recorded what was quoted, so they stay empty
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .add_column(ColumnDef::new(tasks::Column::Rank).string().null())
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .add_column(ColumnDef::new(tasks::Column::Collection).string().null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .drop_column(tasks::Column::Collection)
+            .to_owned()
+        )
+        .await?;
+
+        manager.alter_table(
+            Table::alter()
+            .table(tasks::Entity)
+            .drop_column(tasks::Column::Rank)
+            .to_owned()
+        )
+        .await
+    }
+}
@@ -0,0 +1,33 @@
+use entity::history;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220824_000016_add_history_metadata_uri"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(history::Entity)
+            .add_column(ColumnDef::new(history::Column::MetadataUri).string().null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(history::Entity)
+            .drop_column(history::Column::MetadataUri)
+            .to_owned()
+        )
+        .await
+    }
+}
@@ -0,0 +1,63 @@
+use entity::jobs;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220828_000017_create_jobs_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+            .table(jobs::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(jobs::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(jobs::Column::HistoryId).integer().not_null().unique_key())
+            .col(ColumnDef::new(jobs::Column::PaymentId).integer().not_null())
+            .col(ColumnDef::new(jobs::Column::TaskId).integer().not_null())
+            .col(ColumnDef::new(jobs::Column::Account).string().not_null())
+            .col(ColumnDef::new(jobs::Column::Status).string().not_null())
+            .col(ColumnDef::new(jobs::Column::Attempts).integer().not_null())
+            .col(ColumnDef::new(jobs::Column::RunAt).date_time().not_null())
+            .col(ColumnDef::new(jobs::Column::LockedUntil).date_time().null())
+            .col(ColumnDef::new(jobs::Column::LastError).string().null())
+            .col(ColumnDef::new(jobs::Column::Success).boolean().null())
+            .col(ColumnDef::new(jobs::Column::MetadataUri).string().null())
+            .col(ColumnDef::new(jobs::Column::CreatedAt).date_time().not_null())
+            .col(ColumnDef::new(jobs::Column::FinishedAt).date_time().null())
+            .to_owned()
+        )
+        .await?;
+
+        // -- Workers poll for the oldest runnable job
+        manager.create_index(
+            Index::create()
+            .name("idx-jobs-status-run-at")
+            .table(jobs::Entity)
+            .col(jobs::Column::Status)
+            .col(jobs::Column::RunAt)
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(jobs::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
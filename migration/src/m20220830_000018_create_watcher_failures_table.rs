@@ -0,0 +1,43 @@
+use entity::watcher_failures;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220830_000018_create_watcher_failures_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+            .table(watcher_failures::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(watcher_failures::Column::Id)
+                .integer()
+                .not_null()
+                .auto_increment()
+                .primary_key()
+            )
+            .col(ColumnDef::new(watcher_failures::Column::Cursor).string().not_null())
+            .col(ColumnDef::new(watcher_failures::Column::Signature).string().not_null())
+            .col(ColumnDef::new(watcher_failures::Column::Error).string().not_null())
+            .col(ColumnDef::new(watcher_failures::Column::CreatedAt).date_time().not_null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(
+            Table::drop()
+            .table(watcher_failures::Entity)
+            .to_owned()
+        )
+        .await
+    }
+}
@@ -0,0 +1,33 @@
+use entity::refunds;
+use sea_schema::migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220901_000019_add_refund_block_height"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(refunds::Entity)
+            .add_column(ColumnDef::new(refunds::Column::LastValidBlockHeight).big_integer().null())
+            .to_owned()
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(refunds::Entity)
+            .drop_column(refunds::Column::LastValidBlockHeight)
+            .to_owned()
+        )
+        .await
+    }
+}
@@ -0,0 +1,44 @@
+use entity::payments;
+use sea_schema::migration::prelude::*;
+use sea_orm::{ConnectionTrait, Statement};
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220903_000020_add_payment_quote_expiry"
+    }
+}
+
+/// Payments opened before they carried their own expiry take their task's.
+const BACKFILL: &str =
+    "UPDATE \"payments\" SET \"quote_expires_at\" = (SELECT \"quote_expires_at\" FROM \"tasks\" WHERE \"tasks\".\"id\" = \"payments\".\"task_id\")";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .add_column(ColumnDef::new(payments::Column::QuoteExpiresAt).date_time().null())
+            .to_owned()
+        )
+        .await?;
+
+        let db = manager.get_connection();
+        db.execute(Statement::from_string(db.get_database_backend(), BACKFILL.to_owned()))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+            .table(payments::Entity)
+            .drop_column(payments::Column::QuoteExpiresAt)
+            .to_owned()
+        )
+        .await
+    }
+}
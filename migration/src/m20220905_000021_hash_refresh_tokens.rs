@@ -0,0 +1,30 @@
+use sea_schema::migration::prelude::*;
+use sea_orm::{ConnectionTrait, Statement};
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220905_000021_hash_refresh_tokens"
+    }
+}
+
+/// Refresh tokens are now stored as their SHA-256, so the ones stored in plain
+/// text are dropped. Their wallets sign in again once their access tokens run out.
+const DROP_PLAINTEXT: &str = "DELETE FROM \"refresh_tokens\"";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute(Statement::from_string(db.get_database_backend(), DROP_PLAINTEXT.to_owned()))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // -- Dropped tokens cannot be brought back
+        Ok(())
+    }
+}
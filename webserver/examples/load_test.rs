@@ -0,0 +1,192 @@
+//! Keeps a running server busy with chain-bound requests while timing a cheap
+//! probe endpoint, to check that slow RPC calls do not stall other requests.
+//!
+//! ```text
+//! cargo run --example load_test -- http://127.0.0.1:8000 \
+//!     --token <access token> \
+//!     --path /api/payments/reference/<reference> \
+//!     --concurrency 64 --seconds 30 --max-probe-ms 250
+//! ```
+//!
+//! Exits with an error when the probe's p99 latency exceeds `--max-probe-ms`.
+
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+struct Options {
+    base_url: String,
+    token: Option<String>,
+    path: String,
+    probe: String,
+    concurrency: usize,
+    seconds: u64,
+    max_probe_ms: u128,
+}
+
+impl Options {
+    fn parse() -> Result<Self> {
+        let mut args = std::env::args().skip(1);
+        let mut options = Options {
+            base_url: String::new(),
+            token: std::env::var("METAMUTATE_TOKEN").ok(),
+            path: String::new(),
+            probe: "/api".to_string(),
+            concurrency: 64,
+            seconds: 30,
+            max_probe_ms: 250,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+
+            match arg.as_str() {
+                "--token" => options.token = Some(value()?),
+                "--path" => options.path = value()?,
+                "--probe" => options.probe = value()?,
+                "--concurrency" => options.concurrency = value()?.parse()?,
+                "--seconds" => options.seconds = value()?.parse()?,
+                "--max-probe-ms" => options.max_probe_ms = value()?.parse()?,
+                _ if options.base_url.is_empty() => options.base_url = arg,
+                _ => return Err(anyhow!("Unknown argument {}", arg)),
+            }
+        }
+
+        if options.base_url.is_empty() || options.path.is_empty() {
+            return Err(anyhow!(
+                "Usage: load_test <base url> --path <chain-bound path> [--token <token>] \
+                 [--probe <path>] [--concurrency <n>] [--seconds <n>] [--max-probe-ms <n>]"
+            ));
+        }
+
+        options.base_url = options.base_url.trim_end_matches('/').to_string();
+
+        Ok(options)
+    }
+}
+
+#[derive(Default)]
+struct Timings {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+impl Timings {
+    fn record(&mut self, started: Instant, ok: bool) {
+        self.latencies.push(started.elapsed());
+
+        if !ok {
+            self.errors += 1;
+        }
+    }
+
+    fn percentile(&self, percentile: usize) -> Duration {
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+
+        match latencies.len() {
+            0 => Duration::ZERO,
+            len => latencies[(len * percentile / 100).min(len - 1)],
+        }
+    }
+
+    fn report(&self, name: &str) {
+        println!(
+            "{:<6} {:>6} requests {:>5} errors   p50 {:>6} ms   p95 {:>6} ms   p99 {:>6} ms   max {:>6} ms",
+            name,
+            self.latencies.len(),
+            self.errors,
+            self.percentile(50).as_millis(),
+            self.percentile(95).as_millis(),
+            self.percentile(99).as_millis(),
+            self.percentile(100).as_millis(),
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let options = Arc::new(Options::parse()?);
+    let client = reqwest::Client::new();
+    let running = Arc::new(AtomicBool::new(true));
+    let load = Arc::new(Mutex::new(Timings::default()));
+
+    println!(
+        "{} workers on {} for {}s, probing {}",
+        options.concurrency, options.path, options.seconds, options.probe
+    );
+
+    let mut workers = Vec::new();
+
+    for _ in 0..options.concurrency {
+        let (options, client, running, load) = (
+            options.clone(),
+            client.clone(),
+            running.clone(),
+            load.clone(),
+        );
+
+        workers.push(tokio::spawn(async move {
+            while running.load(Ordering::Relaxed) {
+                let mut request = client.get(format!("{}{}", options.base_url, options.path));
+
+                if let Some(token) = &options.token {
+                    request = request.header("Authorization", token);
+                }
+
+                let started = Instant::now();
+                let ok = match request.send().await {
+                    Ok(response) => !response.status().is_server_error(),
+                    Err(_) => false,
+                };
+
+                load.lock().await.record(started, ok);
+            }
+        }));
+    }
+
+    // -- Probe at a steady rate so its latency reflects how long any other
+    // -- request would wait behind the chain-bound ones
+    let mut probe = Timings::default();
+    let deadline = Instant::now() + Duration::from_secs(options.seconds);
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+
+    while Instant::now() < deadline {
+        interval.tick().await;
+
+        let started = Instant::now();
+        let ok = match client
+            .get(format!("{}{}", options.base_url, options.probe))
+            .send()
+            .await
+        {
+            Ok(response) => response.status().is_success(),
+            Err(_) => false,
+        };
+
+        probe.record(started, ok);
+    }
+
+    running.store(false, Ordering::Relaxed);
+
+    for worker in workers {
+        let _ = worker.await;
+    }
+
+    load.lock().await.report("load");
+    probe.report("probe");
+
+    let p99 = probe.percentile(99).as_millis();
+
+    if p99 > options.max_probe_ms {
+        return Err(anyhow!(
+            "Probe p99 of {} ms exceeds {} ms",
+            p99,
+            options.max_probe_ms
+        ));
+    }
+
+    Ok(())
+}
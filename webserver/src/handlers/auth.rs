@@ -0,0 +1,195 @@
+use crate::util::Claims;
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDateTime, Utc};
+use entity::nonces::{self, Entity as Nonces};
+use entity::refresh_tokens::{self, Entity as RefreshTokens};
+use entity::revoked_tokens::{self, Entity as RevokedTokens};
+use sea_orm::entity::prelude::Uuid;
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::NotSet;
+use sea_orm::{entity::*, query::*, DatabaseConnection};
+use sha2::{Digest, Sha256};
+
+/// Issues a fresh single-use nonce for a login attempt by `pubkey`.
+pub async fn issue_nonce(
+    db: &DatabaseConnection,
+    pubkey: &str,
+    ttl_seconds: i64,
+) -> Result<nonces::Model> {
+    let time_now = Utc::now().naive_utc();
+
+    let nonce = nonces::ActiveModel {
+        id: NotSet,
+        pubkey: Set(pubkey.to_string()),
+        nonce: Set(Uuid::new_v4().to_string()),
+        created_at: Set(time_now),
+        expires_at: Set(time_now + Duration::seconds(ttl_seconds)),
+        consumed_at: Set(None),
+    };
+
+    Ok(nonce.insert(db).await?)
+}
+
+/// Looks up an outstanding nonce, rejecting ones that were used or have expired.
+pub async fn find_nonce(
+    db: &DatabaseConnection,
+    pubkey: &str,
+    nonce: &str,
+) -> Result<nonces::Model> {
+    let found = Nonces::find()
+        .filter(nonces::Column::Pubkey.eq(pubkey))
+        .filter(nonces::Column::Nonce.eq(nonce))
+        .one(db)
+        .await?;
+
+    let nonce = match found {
+        Some(nonce) => nonce,
+        None => return Err(anyhow!("Nonce was not issued for this pubkey")),
+    };
+
+    if nonce.consumed_at.is_some() {
+        return Err(anyhow!("Nonce has already been used"));
+    }
+
+    if nonce.expires_at < Utc::now().naive_utc() {
+        return Err(anyhow!("Nonce has expired"));
+    }
+
+    Ok(nonce)
+}
+
+/// Marks the nonce as used and retires every other outstanding nonce for the
+/// same pubkey. Only one caller can win the update, so a signature can never
+/// be exchanged for a token twice.
+pub async fn consume_nonce(db: &DatabaseConnection, nonce: &nonces::Model) -> Result<()> {
+    let time_now = Utc::now().naive_utc();
+
+    let consumed = Nonces::update_many()
+        .col_expr(nonces::Column::ConsumedAt, Expr::value(time_now))
+        .filter(nonces::Column::Id.eq(nonce.id))
+        .filter(nonces::Column::ConsumedAt.is_null())
+        .exec(db)
+        .await?;
+
+    if consumed.rows_affected != 1 {
+        return Err(anyhow!("Nonce has already been used"));
+    }
+
+    Nonces::update_many()
+        .col_expr(nonces::Column::ConsumedAt, Expr::value(time_now))
+        .filter(nonces::Column::Pubkey.eq(nonce.pubkey.as_str()))
+        .filter(nonces::Column::ConsumedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Issues a new opaque refresh token for `pubkey`. Only its hash is stored, so
+/// the token itself is returned alongside the row.
+pub async fn issue_refresh_token(
+    db: &DatabaseConnection,
+    pubkey: &str,
+    ttl_days: i64,
+) -> Result<(String, refresh_tokens::Model)> {
+    let time_now = Utc::now().naive_utc();
+    let token = Uuid::new_v4().to_string();
+
+    let refresh_token = refresh_tokens::ActiveModel {
+        id: NotSet,
+        pubkey: Set(pubkey.to_string()),
+        token: Set(hash_refresh_token(&token)),
+        created_at: Set(time_now),
+        expires_at: Set(time_now + Duration::days(ttl_days)),
+        revoked_at: Set(None),
+    };
+
+    Ok((token, refresh_token.insert(db).await?))
+}
+
+/// Exchanges a refresh token for its owner, revoking it in the process.
+/// Presenting a token that was already rotated revokes every refresh token of
+/// that wallet, since it means the token has leaked.
+pub async fn rotate_refresh_token(db: &DatabaseConnection, token: &str) -> Result<String> {
+    let found = RefreshTokens::find()
+        .filter(refresh_tokens::Column::Token.eq(hash_refresh_token(token)))
+        .one(db)
+        .await?;
+
+    let refresh_token = match found {
+        Some(refresh_token) => refresh_token,
+        None => return Err(anyhow!("Refresh token is invalid")),
+    };
+
+    if refresh_token.revoked_at.is_some() {
+        revoke_refresh_tokens(db, &refresh_token.pubkey).await?;
+        return Err(anyhow!("Refresh token has been revoked"));
+    }
+
+    if refresh_token.expires_at < Utc::now().naive_utc() {
+        return Err(anyhow!("Refresh token has expired"));
+    }
+
+    let revoked = RefreshTokens::update_many()
+        .col_expr(refresh_tokens::Column::RevokedAt, Expr::value(Utc::now().naive_utc()))
+        .filter(refresh_tokens::Column::Id.eq(refresh_token.id))
+        .filter(refresh_tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    if revoked.rows_affected != 1 {
+        return Err(anyhow!("Refresh token has been revoked"));
+    }
+
+    Ok(refresh_token.pubkey)
+}
+
+/// Revokes every outstanding refresh token of `pubkey`.
+pub async fn revoke_refresh_tokens(db: &DatabaseConnection, pubkey: &str) -> Result<()> {
+    RefreshTokens::update_many()
+        .col_expr(refresh_tokens::Column::RevokedAt, Expr::value(Utc::now().naive_utc()))
+        .filter(refresh_tokens::Column::Pubkey.eq(pubkey))
+        .filter(refresh_tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Hex SHA-256 a refresh token is stored and looked up by, so a leaked
+/// database holds no usable tokens.
+fn hash_refresh_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Adds an access token to the revocation store until it would have expired.
+pub async fn revoke_access_token(db: &DatabaseConnection, claims: &Claims) -> Result<()> {
+    let expires_at = NaiveDateTime::from_timestamp(claims.exp as i64, 0);
+
+    let revoked = revoked_tokens::ActiveModel {
+        id: NotSet,
+        jti: Set(claims.jti.clone()),
+        pubkey: Set(claims.sub.clone()),
+        expires_at: Set(expires_at),
+        revoked_at: Set(Utc::now().naive_utc()),
+    };
+
+    revoked.insert(db).await?;
+
+    // -- Expired entries can no longer be presented, so there is no need to keep them
+    RevokedTokens::delete_many()
+        .filter(revoked_tokens::Column::ExpiresAt.lt(Utc::now().naive_utc()))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn is_token_revoked(db: &DatabaseConnection, jti: &str) -> Result<bool> {
+    let found = RevokedTokens::find()
+        .filter(revoked_tokens::Column::Jti.eq(jti))
+        .one(db)
+        .await?;
+
+    Ok(found.is_some())
+}
@@ -1,2 +1,6 @@
+pub mod auth;
 pub mod metadata;
-pub mod payment;
\ No newline at end of file
+pub mod payment;
+pub mod refund;
+pub mod report;
+pub mod status;
\ No newline at end of file
@@ -1,25 +1,270 @@
-use std::time::Duration;
+use std::str::FromStr;
 
-use super::metadata::{get_rank_attribute, verify_metadata, fetch_inner_metadata};
+use super::metadata::{apply_update, get_rank_attribute, prepare_update, verify_metadata, fetch_inner_metadata, RankUp};
+use super::refund::{open_refund, REFUND_PENDING};
+use super::status::{record_transition, transition, transition_with, TransitionError};
 use anyhow::{anyhow, Result};
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::{commitment_config::CommitmentConfig};
-
-pub async fn check_price(mint_address: &str) -> Result<i32> {
-    let url = "https://sol.gibki.io".to_string();
-    let timeout = Duration::from_secs(120);
-    let commitment_config = CommitmentConfig::processed();
-    let rpc = RpcClient::new_with_timeout_and_commitment(
-        url,
-        timeout,
-        commitment_config,
-    );
-    
-    let metadata = match verify_metadata(&rpc, mint_address).await {
+use chrono::{NaiveDateTime, Utc};
+use crate::jobs;
+use crate::rpc::{parse_commitment, SolanaRpc};
+use crate::util::{is_unique_violation, params::Base58Pubkey, SysResponse, WebResponse};
+use crate::Config;
+use entity::history::{self, Entity as History, HistoryStatus};
+use entity::jobs::Model as Job;
+use entity::payments::{self, Entity as Payments, PaymentStatus};
+use entity::tasks::{self, Entity as Tasks, TaskStatus};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize};
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::NotSet;
+use sea_orm::{entity::*, query::*, ConnectionTrait, DatabaseConnection, DbErr, TransactionTrait};
+use serde_json::json;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::CompiledInstruction,
+    pubkey::Pubkey,
+    signature::Signature,
+    system_instruction::SystemInstruction,
+    system_program,
+    transaction::VersionedTransaction,
+};
+use std::fmt;
+use solana_transaction_status::UiTransactionEncoding;
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::instruction::TokenInstruction;
+
+const NATIVE_CURRENCY: &str = "SOL";
+
+/// Memo prefix that ties a transfer to a payment row, followed by its id.
+pub const PAYMENT_MEMO_PREFIX: &str = "metamutate:payment:";
+
+/// `[default.currency]` section of `Rocket.toml`. Without a mint, rank-ups
+/// are paid in native SOL.
+#[derive(Deserialize)]
+pub struct CurrencyConfig {
+    pub mint: Option<Base58Pubkey>,
+    pub decimals: u8,
+    pub base_units_per_price: u64,
+}
+
+impl Default for CurrencyConfig {
+    fn default() -> Self {
+        CurrencyConfig {
+            mint: None,
+            decimals: 9,
+            base_units_per_price: 10_000_000,
+        }
+    }
+}
+
+impl CurrencyConfig {
+    pub fn currency(&self) -> Currency {
+        match self.mint {
+            Some(mint) => Currency::Token {
+                mint: mint.0,
+                decimals: self.decimals,
+            },
+            None => Currency::Sol,
+        }
+    }
+
+    /// Converts a rank-up price into base units (lamports or token units).
+    pub fn base_amount(&self, price: i32) -> i64 {
+        (price.max(0) as i64).saturating_mul(self.base_units_per_price as i64)
+    }
+}
+
+/// What a payment has to be made in.
+pub enum Currency {
+    Sol,
+    Token { mint: Pubkey, decimals: u8 },
+}
+
+impl Currency {
+    /// Reads back a currency as stored on a task or payment row.
+    pub fn parse(currency: &str, decimals: i32) -> Result<Self> {
+        if currency == NATIVE_CURRENCY {
+            return Ok(Currency::Sol);
+        }
+
+        match Pubkey::from_str(currency) {
+            Ok(mint) => Ok(Currency::Token {
+                mint,
+                decimals: decimals as u8,
+            }),
+            Err(_) => Err(anyhow!("Unknown currency {}", currency)),
+        }
+    }
+
+    /// Name stored alongside amounts: the token mint, or "SOL".
+    pub fn name(&self) -> String {
+        match self {
+            Currency::Sol => NATIVE_CURRENCY.to_string(),
+            Currency::Token { mint, .. } => mint.to_string(),
+        }
+    }
+
+    pub fn decimals(&self) -> u8 {
+        match self {
+            Currency::Sol => 9,
+            Currency::Token { decimals, .. } => *decimals,
+        }
+    }
+}
+
+/// Fields of a Solana Pay transfer request.
+pub struct TransferRequest<'a> {
+    pub recipient: &'a Pubkey,
+    pub currency: &'a Currency,
+    pub base_amount: i64,
+    pub reference: &'a Pubkey,
+    pub label: &'a str,
+    pub message: &'a str,
+    pub memo: &'a str,
+}
+
+impl TransferRequest<'_> {
+    /// Renders the `solana:` URL wallets open from a link or QR code.
+    pub fn to_url(&self) -> String {
+        let encode = |value: &str| utf8_percent_encode(value, NON_ALPHANUMERIC).to_string();
+
+        let mut url = format!(
+            "solana:{}?amount={}",
+            self.recipient,
+            format_amount(self.base_amount, self.currency.decimals())
+        );
+
+        if let Currency::Token { mint, .. } = self.currency {
+            url.push_str(&format!("&spl-token={}", mint));
+        }
+
+        url.push_str(&format!(
+            "&reference={}&label={}&message={}&memo={}",
+            self.reference,
+            encode(self.label),
+            encode(self.message),
+            encode(self.memo)
+        ));
+
+        url
+    }
+}
+
+/// Whether a price quote expiring at `expires_at` still holds at `at`. Tasks
+/// without a quote expiry predate quotes and have to be requoted.
+pub fn quote_valid(expires_at: Option<NaiveDateTime>, at: NaiveDateTime) -> bool {
+    match expires_at {
+        Some(expires_at) => at <= expires_at,
+        None => false,
+    }
+}
+
+/// Where and when a verified payment landed on chain.
+pub struct ConfirmedPayment {
+    pub slot: u64,
+    pub block_time: Option<i64>,
+}
+
+/// Outcome of a payment that was confirmed and ran its rank-up.
+pub struct ProcessedPayment {
+    pub slot: u64,
+    pub job_id: i32,
+}
+
+#[derive(Debug)]
+pub enum PaymentError {
+    PaymentNotFound,
+    TaskNotFound,
+    AlreadyConfirmed,
+    SignatureUsed,
+    QuoteExpired,
+    Verification(String),
+    /// The transaction could not be fetched yet and may still confirm.
+    Unavailable(String),
+    Mutation(String),
+    Database(String),
+}
+
+impl PaymentError {
+    pub fn status(&self) -> Status {
+        match self {
+            PaymentError::PaymentNotFound | PaymentError::TaskNotFound => Status::NotFound,
+            PaymentError::AlreadyConfirmed | PaymentError::SignatureUsed => Status::Conflict,
+            PaymentError::QuoteExpired => Status::Gone,
+            PaymentError::Verification(_) | PaymentError::Unavailable(_) => {
+                Status::PaymentRequired
+            }
+            PaymentError::Mutation(_) | PaymentError::Database(_) => Status::InternalServerError,
+        }
+    }
+
+    pub fn response(&self) -> WebResponse {
+        let data = match self {
+            PaymentError::Verification(reason) | PaymentError::Unavailable(reason) => {
+                json!({ "error": "Payment verification failed", "reason": reason })
+            }
+            PaymentError::QuoteExpired | PaymentError::Mutation(_) => {
+                json!({ "error": self.to_string(), "refund": REFUND_PENDING })
+            }
+            _ => json!({ "error": self.to_string() }),
+        };
+        let response = SysResponse { data };
+
+        (self.status(), Json(response))
+    }
+}
+
+impl From<TransitionError> for PaymentError {
+    fn from(e: TransitionError) -> Self {
+        match e {
+            TransitionError::Stale => PaymentError::AlreadyConfirmed,
+            TransitionError::Database(e) if is_unique_violation(&e) => PaymentError::SignatureUsed,
+            e => PaymentError::Database(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaymentError::PaymentNotFound => write!(f, "Payment does not exist"),
+            PaymentError::TaskNotFound => write!(f, "Task does not exist"),
+            PaymentError::AlreadyConfirmed => write!(f, "Payment has already been confirmed"),
+            PaymentError::SignatureUsed => write!(f, "Transaction signature has already been used"),
+            PaymentError::QuoteExpired => write!(f, "Price quote expired before the payment landed"),
+            PaymentError::Verification(reason) | PaymentError::Unavailable(reason) => {
+                write!(f, "Payment verification failed: {}", reason)
+            }
+            PaymentError::Mutation(reason) => write!(f, "{}", reason),
+            PaymentError::Database(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Price of the next rank-up for a mint, with the rank and collection it was
+/// quoted for. Mints outside a verified collection have no collection.
+pub struct Quote {
+    pub price: i32,
+    pub rank: String,
+    pub collection: Option<String>,
+}
+
+pub async fn check_price(rpc: &SolanaRpc, mint_address: &str) -> Result<Quote> {
+    let metadata = match verify_metadata(rpc, mint_address).await {
         Ok(metadata) => metadata,
         Err(e) => return Err(anyhow!(format!("verify_metadata: {}", e)))
     };
 
+    // -- Collections are told apart by their verified collection mint
+    let collection = metadata
+        .collection
+        .as_ref()
+        .filter(|collection| collection.verified)
+        .map(|collection| collection.key.to_string());
+
     let inner = match fetch_inner_metadata(metadata, mint_address).await {
         Ok(inner) => inner,
         Err(e) => return Err(anyhow!(format!("fetch_inner_metadata: {}", e)))
@@ -36,5 +281,569 @@ pub async fn check_price(mint_address: &str) -> Result<i32> {
         _ => return Err(anyhow!("Not a valid rank to use for rankup")),
     };
 
-    Ok(price)
-}
\ No newline at end of file
+    Ok(Quote {
+        price,
+        rank: rank.value,
+        collection,
+    })
+}
+
+/// Fetches the transaction behind `signature` at the given commitment level
+/// and checks that it succeeded and transferred at least `amount` base units
+/// of `currency` from `payer` to `treasury`.
+pub async fn verify_payment(
+    rpc: &SolanaRpc,
+    signature: &Signature,
+    payer: &Pubkey,
+    treasury: &Pubkey,
+    currency: &Currency,
+    amount: u64,
+    commitment: &str,
+) -> Result<ConfirmedPayment, PaymentError> {
+    let commitment = match parse_commitment(commitment) {
+        Ok(commitment) => commitment,
+        Err(e) => return Err(PaymentError::Database(e.to_string())),
+    };
+    let (transaction, confirmed) = fetch_transaction(rpc, signature, commitment).await?;
+
+    let paid = transferred(&transaction, payer, treasury, currency);
+
+    if paid == 0 {
+        return Err(PaymentError::Verification(format!(
+            "Transaction does not transfer {} from the payer to the treasury",
+            currency.name()
+        )));
+    }
+
+    if paid < amount {
+        return Err(PaymentError::Verification(format!(
+            "Transaction paid {} base units, expected {}",
+            paid, amount
+        )));
+    }
+
+    Ok(confirmed)
+}
+
+/// Base units of `currency` a transaction moves from `payer` to `treasury`.
+/// Token payments have to land in the treasury's associated token account.
+pub fn transferred(
+    transaction: &VersionedTransaction,
+    payer: &Pubkey,
+    treasury: &Pubkey,
+    currency: &Currency,
+) -> u64 {
+    let keys = transaction.message.static_account_keys();
+    let instructions = transaction.message.instructions();
+
+    match currency {
+        Currency::Sol => sol_transferred(keys, instructions, payer, treasury),
+        Currency::Token { mint, decimals } => {
+            let treasury_account = get_associated_token_address(treasury, mint);
+            token_transferred(keys, instructions, payer, &treasury_account, mint, *decimals)
+        }
+    }
+}
+
+/// Fetches a transaction that succeeded at the given commitment level. A
+/// transaction the RPC cannot return yet is `Unavailable`, since it may still
+/// land.
+pub async fn fetch_transaction(
+    rpc: &SolanaRpc,
+    signature: &Signature,
+    commitment: CommitmentConfig,
+) -> Result<(VersionedTransaction, ConfirmedPayment), PaymentError> {
+    let signature = *signature;
+
+    let confirmed = rpc.call(move |client| {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(commitment),
+            max_supported_transaction_version: Some(0),
+        };
+        client.get_transaction_with_config(&signature, config)
+    })
+    .await;
+
+    let confirmed = match confirmed {
+        Ok(confirmed) => confirmed,
+        Err(_) => {
+            return Err(PaymentError::Unavailable(format!(
+                "Transaction not found at {:?} commitment",
+                commitment.commitment
+            )))
+        }
+    };
+
+    match &confirmed.transaction.meta {
+        Some(meta) if meta.err.is_none() => (),
+        Some(meta) => {
+            return Err(PaymentError::Verification(format!(
+                "Transaction failed: {:?}",
+                meta.err
+            )))
+        }
+        None => {
+            return Err(PaymentError::Unavailable(
+                "Transaction status is unavailable".to_string(),
+            ))
+        }
+    }
+
+    let transaction = match confirmed.transaction.transaction.decode() {
+        Some(transaction) => transaction,
+        None => {
+            return Err(PaymentError::Verification(
+                "Transaction could not be decoded".to_string(),
+            ))
+        }
+    };
+
+    Ok((
+        transaction,
+        ConfirmedPayment {
+            slot: confirmed.slot,
+            block_time: confirmed.block_time,
+        },
+    ))
+}
+
+/// Sums the lamports moved from `payer` to `treasury` by system transfers.
+fn sol_transferred(
+    keys: &[Pubkey],
+    instructions: &[CompiledInstruction],
+    payer: &Pubkey,
+    treasury: &Pubkey,
+) -> u64 {
+    let account = |index: Option<&u8>| index.and_then(|index| keys.get(*index as usize));
+    let mut paid: u64 = 0;
+
+    for instruction in instructions {
+        if keys.get(instruction.program_id_index as usize) != Some(&system_program::id()) {
+            continue;
+        }
+
+        let from = account(instruction.accounts.first());
+        let to = account(instruction.accounts.get(1));
+
+        if let Ok(SystemInstruction::Transfer { lamports }) =
+            bincode::deserialize(&instruction.data)
+        {
+            if from == Some(payer) && to == Some(treasury) {
+                paid = paid.saturating_add(lamports);
+            }
+        }
+    }
+
+    paid
+}
+
+/// Sums the tokens moved into `destination` by SPL `transfer` and
+/// `transferChecked` instructions signed by `payer`.
+fn token_transferred(
+    keys: &[Pubkey],
+    instructions: &[CompiledInstruction],
+    payer: &Pubkey,
+    destination: &Pubkey,
+    mint: &Pubkey,
+    decimals: u8,
+) -> u64 {
+    let account = |index: Option<&u8>| index.and_then(|index| keys.get(*index as usize));
+    let mut paid: u64 = 0;
+
+    for instruction in instructions {
+        if keys.get(instruction.program_id_index as usize) != Some(&spl_token::id()) {
+            continue;
+        }
+
+        let accounts = &instruction.accounts;
+
+        let amount = match TokenInstruction::unpack(&instruction.data) {
+            // -- source, destination, authority
+            Ok(TokenInstruction::Transfer { amount }) => {
+                let to = account(accounts.get(1));
+                let authority = account(accounts.get(2));
+
+                if to != Some(destination) || authority != Some(payer) {
+                    continue;
+                }
+
+                amount
+            }
+            // -- source, mint, destination, authority
+            Ok(TokenInstruction::TransferChecked {
+                amount,
+                decimals: transfer_decimals,
+            }) => {
+                let transfer_mint = account(accounts.get(1));
+                let to = account(accounts.get(2));
+                let authority = account(accounts.get(3));
+
+                if transfer_mint != Some(mint)
+                    || transfer_decimals != decimals
+                    || to != Some(destination)
+                    || authority != Some(payer)
+                {
+                    continue;
+                }
+
+                amount
+            }
+            _ => continue,
+        };
+
+        paid = paid.saturating_add(amount);
+    }
+
+    paid
+}
+
+/// Looks up the oldest successful transaction that includes `reference`,
+/// which is the one confirming a Solana Pay transfer request.
+pub async fn find_reference(
+    rpc: &SolanaRpc,
+    reference: &Pubkey,
+    commitment: &str,
+) -> Result<Option<Signature>> {
+    let commitment = parse_commitment(commitment)?;
+    let reference = *reference;
+
+    let statuses = rpc.call(move |client| {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            commitment: Some(commitment),
+            ..GetConfirmedSignaturesForAddress2Config::default()
+        };
+        client.get_signatures_for_address_with_config(&reference, config)
+    });
+
+    let statuses = match statuses.await {
+        Ok(statuses) => statuses,
+        Err(e) => return Err(anyhow!("Failed to look up reference: {}", e)),
+    };
+
+    // -- Signatures come back newest first
+    let signature = statuses
+        .iter()
+        .rev()
+        .find(|status| status.err.is_none())
+        .and_then(|status| Signature::from_str(&status.signature).ok());
+
+    Ok(signature)
+}
+
+/// Formats base units as a decimal amount without trailing zeros.
+pub fn format_amount(base_amount: i64, decimals: u8) -> String {
+    let base_amount = base_amount.max(0) as u128;
+    let scale = 10u128.pow(decimals as u32);
+    let whole = base_amount / scale;
+    let fraction = base_amount % scale;
+
+    if fraction == 0 {
+        return whole.to_string();
+    }
+
+    let fraction = format!("{:0width$}", fraction, width = decimals as usize);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+/// Verifies `signature` against a pending payment, claims both, records the
+/// history row and runs the rank-up, opening a refund if the rank-up fails.
+/// Shared by the payment hook and the treasury watcher.
+pub async fn process_payment(
+    db: &DatabaseConnection,
+    config: &Config,
+    rpc: &SolanaRpc,
+    payment_id: i32,
+    signature: &Signature,
+) -> Result<ProcessedPayment, PaymentError> {
+    let payment = match Payments::find_by_id(payment_id).one(db).await {
+        Ok(Some(payment)) => payment,
+        Ok(None) => return Err(PaymentError::PaymentNotFound),
+        Err(_) => return Err(PaymentError::Database("Failed to fetch payment".to_string())),
+    };
+
+    let task = match Tasks::find_by_id(payment.task_id).one(db).await {
+        Ok(Some(task)) => task,
+        Ok(None) => return Err(PaymentError::TaskNotFound),
+        Err(_) => return Err(PaymentError::Database("Database query failed".to_string())),
+    };
+
+    if payment.status != PaymentStatus::Pending {
+        return Err(PaymentError::AlreadyConfirmed);
+    }
+
+    let tx_id = signature.to_string();
+
+    let used_by_payment = Payments::find()
+        .filter(payments::Column::Tx.eq(tx_id.as_str()))
+        .one(db)
+        .await;
+    let used_by_history = History::find()
+        .filter(history::Column::Signature.eq(tx_id.as_str()))
+        .one(db)
+        .await;
+
+    match (used_by_payment, used_by_history) {
+        (Ok(None), Ok(None)) => (),
+        (Ok(_), Ok(_)) => return Err(PaymentError::SignatureUsed),
+        _ => return Err(PaymentError::Database("Database query failed".to_string())),
+    }
+
+    let payer: Base58Pubkey = match payment.account.parse() {
+        Ok(payer) => payer,
+        Err(_) => {
+            return Err(PaymentError::Database(
+                "Payment account is not a valid pubkey".to_string(),
+            ))
+        }
+    };
+
+    let currency = match Currency::parse(&payment.currency, payment.decimals) {
+        Ok(currency) => currency,
+        Err(e) => return Err(PaymentError::Database(e.to_string())),
+    };
+
+    let verified = verify_payment(
+        rpc,
+        signature,
+        &payer.0,
+        &config.treasury.0,
+        &currency,
+        payment.base_amount.max(0) as u64,
+        &config.payment_commitment,
+    )
+    .await;
+
+    let confirmed = match verified {
+        Ok(confirmed) => confirmed,
+        Err(e) => {
+            if let PaymentError::Verification(reason) | PaymentError::Unavailable(reason) = &e {
+                let mut failed_payment: payments::ActiveModel = payment.clone().into();
+                failed_payment.failure_reason = Set(Some(reason.clone()));
+                let _ = failed_payment.save(db).await;
+            }
+
+            return Err(e);
+        }
+    };
+
+    let confirmed_at = confirmed
+        .block_time
+        .map(|time| NaiveDateTime::from_timestamp(time, 0))
+        .unwrap_or_else(|| Utc::now().naive_utc());
+
+    // -- A payment holds the quote it was opened under, so requoting the task
+    // -- neither reprices nor extends it
+    if !quote_valid(payment.quote_expires_at, confirmed_at) {
+        return Err(reject_expired(db, &payment, &task, &tx_id).await);
+    }
+
+    // -- Everything from claiming the payment to queueing its rank-up commits
+    // -- together, so a crash never leaves a paid payment without a job
+    let txn = match db.begin().await {
+        Ok(txn) => txn,
+        Err(e) => return Err(PaymentError::Database(e.to_string())),
+    };
+
+    // -- Claim the signature and the payment in one statement so concurrent
+    // -- hooks for the same payment or transaction cannot both go through
+    transition_with(&txn, payment.id, PaymentStatus::Pending, PaymentStatus::Paid, |update| {
+        update
+            .col_expr(payments::Column::Tx, Expr::value(tx_id.clone()))
+            .col_expr(payments::Column::ConfirmedAt, Expr::value(confirmed_at))
+            .col_expr(
+                payments::Column::FailureReason,
+                Expr::value(Option::<String>::None),
+            )
+            .filter(payments::Column::Tx.eq("none"))
+    })
+    .await?;
+
+    // -- A task is only paid for once, so a second payment is owed back
+    match transition(&txn, task.id, TaskStatus::AwaitingPayment, TaskStatus::Paid).await {
+        Ok(_) => (),
+        Err(TransitionError::Stale) => {
+            let reason = "Task is no longer awaiting payment".to_string();
+            transition(&txn, payment.id, PaymentStatus::Paid, PaymentStatus::Failed).await?;
+
+            if open_refund(&txn, &payment, &reason).await.is_err() {
+                return Err(PaymentError::Database("Failed to record refund".to_string()));
+            }
+
+            if let Err(e) = txn.commit().await {
+                return Err(PaymentError::Database(e.to_string()));
+            }
+
+            return Err(PaymentError::Mutation(reason));
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    transition(&txn, payment.id, PaymentStatus::Paid, PaymentStatus::Processing).await?;
+    transition(&txn, task.id, TaskStatus::Paid, TaskStatus::Processing).await?;
+
+    let new_history = history::ActiveModel {
+        id: NotSet,
+        account: Set(payment.account.clone()),
+        mint_address: Set(task.mint_address.clone()),
+        finished_at: Set(Utc::now().naive_utc()),
+        payment_id: Set(payment.id),
+        task_id: Set(task.id),
+        signature: Set(tx_id),
+        price: Set(task.price),
+        success: Set(false),
+        status: Set(HistoryStatus::Processing),
+        metadata_uri: Set(None),
+    };
+
+    let queued = async {
+        let history = new_history.insert(&txn).await?;
+        let job = jobs::enqueue(&txn, &history).await?;
+        record_transition(&txn, history.id, None, HistoryStatus::Processing).await?;
+
+        Ok::<_, DbErr>(job)
+    };
+
+    let job = match queued.await {
+        Ok(job) => job,
+        Err(e) if is_unique_violation(&e) => return Err(PaymentError::SignatureUsed),
+        Err(e) => return Err(PaymentError::Database(e.to_string())),
+    };
+
+    if let Err(e) = txn.commit().await {
+        return Err(PaymentError::Database(e.to_string()));
+    }
+
+    Ok(ProcessedPayment {
+        slot: confirmed.slot,
+        job_id: job.id,
+    })
+}
+
+/// Runs a queued rank-up: rolls and publishes the new metadata, sets it on
+/// chain and moves the history, payment and task to `applied`. The published
+/// URI is kept on the job, so a retry only repeats the on-chain update.
+pub async fn run_rank_up(
+    db: &DatabaseConnection,
+    config: &Config,
+    rpc: &SolanaRpc,
+    job: &mut Job,
+) -> Result<RankUp> {
+    let task = match Tasks::find_by_id(job.task_id).one(db).await? {
+        Some(task) => task,
+        None => return Err(anyhow!("Task does not exist")),
+    };
+
+    let rank_up = match (job.success, &job.metadata_uri) {
+        (Some(successful), Some(uri)) => RankUp {
+            successful,
+            uri: uri.clone(),
+        },
+        _ => {
+            // -- Each collection publishes its metadata to its own storage backend
+            let storage = config.storage.for_collection(task.collection.as_deref()).backend();
+            let rank_up = prepare_update(rpc, &task.mint_address, storage.as_ref()).await?;
+            jobs::record_prepared(db, job, &rank_up).await?;
+
+            rank_up
+        }
+    };
+
+    // -- Two workers must never set different rolls on chain
+    jobs::hold(db, job, &config.jobs).await?;
+    apply_update(rpc, &config.update_authority, &task.mint_address, &rank_up.uri).await?;
+
+    // -- A failed rank roll still applied the paid mutation, so only `success`
+    // -- records the roll
+    let applied = transition_with(
+        db,
+        job.history_id,
+        HistoryStatus::Processing,
+        HistoryStatus::Applied,
+        |update| {
+            update
+                .col_expr(history::Column::Success, Expr::value(rank_up.successful))
+                .col_expr(history::Column::MetadataUri, Expr::value(rank_up.uri.clone()))
+                .col_expr(history::Column::FinishedAt, Expr::value(Utc::now().naive_utc()))
+        },
+    )
+    .await;
+
+    unless_done(applied)?;
+    unless_done(transition(db, job.payment_id, PaymentStatus::Processing, PaymentStatus::Applied).await)?;
+    unless_done(transition(db, job.task_id, TaskStatus::Processing, TaskStatus::Applied).await)?;
+
+    Ok(rank_up)
+}
+
+/// Fails a rank-up that ran out of attempts. The payer has been charged, so
+/// the payment is owed back.
+pub async fn fail_rank_up<C: ConnectionTrait>(db: &C, job: &Job, reason: &str) -> Result<()> {
+    unless_done(transition(db, job.history_id, HistoryStatus::Processing, HistoryStatus::Failed).await)?;
+    unless_done(transition(db, job.payment_id, PaymentStatus::Processing, PaymentStatus::Failed).await)?;
+    unless_done(transition(db, job.task_id, TaskStatus::Processing, TaskStatus::Failed).await)?;
+
+    if let Some(payment) = Payments::find_by_id(job.payment_id).one(db).await? {
+        open_refund(db, &payment, reason).await?;
+    }
+
+    Ok(())
+}
+
+/// Treats a transition an earlier attempt of the same job already made as done.
+fn unless_done(result: Result<(), TransitionError>) -> Result<()> {
+    match result {
+        Ok(_) | Err(TransitionError::Stale) => Ok(()),
+        Err(e) => Err(anyhow!("{}", e)),
+    }
+}
+
+/// Claims the signature of a payment that landed after its quote expired so it
+/// cannot be reused, and owes the amount back to the payer. The claim and the
+/// refund commit together, so a claimed payment is never left without one.
+async fn reject_expired(
+    db: &DatabaseConnection,
+    payment: &payments::Model,
+    task: &tasks::Model,
+    tx_id: &str,
+) -> PaymentError {
+    let reason = PaymentError::QuoteExpired.to_string();
+
+    let txn = match db.begin().await {
+        Ok(txn) => txn,
+        Err(e) => return PaymentError::Database(e.to_string()),
+    };
+
+    let claim_payment = transition_with(
+        &txn,
+        payment.id,
+        PaymentStatus::Pending,
+        PaymentStatus::Expired,
+        |update| {
+            update
+                .col_expr(payments::Column::Tx, Expr::value(tx_id.to_string()))
+                .col_expr(payments::Column::FailureReason, Expr::value(reason.clone()))
+                .filter(payments::Column::Tx.eq("none"))
+        },
+    )
+    .await;
+
+    if let Err(e) = claim_payment {
+        return e.into();
+    }
+
+    // -- The task may already have been marked expired by a later request
+    match transition(&txn, task.id, TaskStatus::AwaitingPayment, TaskStatus::Expired).await {
+        Ok(_) | Err(TransitionError::Stale) => (),
+        Err(e) => return e.into(),
+    }
+
+    if open_refund(&txn, payment, &reason).await.is_err() {
+        return PaymentError::Database("Failed to record refund".to_string());
+    }
+
+    match txn.commit().await {
+        Ok(_) => PaymentError::QuoteExpired,
+        Err(e) => PaymentError::Database(e.to_string()),
+    }
+}
@@ -0,0 +1,397 @@
+use super::payment::Currency;
+use super::status::transition;
+use crate::rpc::{parse_commitment, SolanaRpc};
+use crate::util::{SysResponse, WebResponse};
+use crate::Config;
+use chrono::{Duration, Utc};
+use entity::payments::{self, Entity as Payments, PaymentStatus};
+use entity::refunds::{self, Entity as Refunds};
+use entity::tasks::{Entity as Tasks, TaskStatus};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::NotSet;
+use sea_orm::{entity::*, query::*, ConnectionTrait, DatabaseConnection, DbErr};
+use serde_json::json;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
+use std::{fmt, str::FromStr};
+
+pub const REFUND_PENDING: &str = "pending";
+pub const REFUND_PROCESSING: &str = "processing";
+pub const REFUND_SENT: &str = "sent";
+
+/// Every status a refund row can be in.
+pub const REFUND_STATUSES: [&str; 3] = [REFUND_PENDING, REFUND_PROCESSING, REFUND_SENT];
+
+/// Memo prefix that ties a refund transfer to its ledger row.
+const REFUND_MEMO_PREFIX: &str = "metamutate:refund:";
+
+/// How long an attempt sent before block heights were recorded is assumed to
+/// stay valid. Blockhashes expire after about two minutes.
+const UNRECORDED_EXPIRY_MINUTES: i64 = 5;
+
+/// What became of the last signed transfer of a refund.
+enum Attempt {
+    /// The transfer landed, so the refund is paid.
+    Landed(Signature),
+    /// The transfer can still land until its blockhash expires.
+    Unsettled,
+    /// The transfer can no longer land, so a new one can be signed.
+    Dropped,
+}
+
+#[derive(Debug)]
+pub enum RefundError {
+    NotFound,
+    AlreadySent,
+    InProgress,
+    Signer(String),
+    Chain(String),
+    Database(String),
+}
+
+impl RefundError {
+    pub fn status(&self) -> Status {
+        match self {
+            RefundError::NotFound => Status::NotFound,
+            RefundError::AlreadySent | RefundError::InProgress => Status::Conflict,
+            RefundError::Signer(_) | RefundError::Database(_) => Status::InternalServerError,
+            RefundError::Chain(_) => Status::BadGateway,
+        }
+    }
+
+    pub fn response(&self) -> WebResponse {
+        let data = json!({ "error": self.to_string() });
+        let response = SysResponse { data };
+
+        (self.status(), Json(response))
+    }
+}
+
+impl fmt::Display for RefundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefundError::NotFound => write!(f, "Refund does not exist"),
+            RefundError::AlreadySent => write!(f, "Refund has already been sent"),
+            RefundError::InProgress => write!(f, "Refund is already being sent"),
+            RefundError::Signer(reason) => write!(f, "{}", reason),
+            RefundError::Chain(reason) => write!(f, "Refund transfer failed: {}", reason),
+            RefundError::Database(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl From<DbErr> for RefundError {
+    fn from(e: DbErr) -> Self {
+        RefundError::Database(e.to_string())
+    }
+}
+
+/// Records that a confirmed payment is owed back to its payer. A payment only
+/// ever gets one refund row.
+pub async fn open_refund<C: ConnectionTrait>(
+    db: &C,
+    payment: &payments::Model,
+    reason: &str,
+) -> Result<(), DbErr> {
+    let exists = Refunds::find()
+        .filter(refunds::Column::PaymentId.eq(payment.id))
+        .one(db)
+        .await?;
+
+    if exists.is_some() {
+        return Ok(());
+    }
+
+    let refund = refunds::ActiveModel {
+        id: NotSet,
+        payment_id: Set(payment.id),
+        account: Set(payment.account.clone()),
+        currency: Set(payment.currency.clone()),
+        decimals: Set(payment.decimals),
+        base_amount: Set(payment.base_amount),
+        reason: Set(reason.to_string()),
+        status: Set(REFUND_PENDING.to_string()),
+        signature: Set(None),
+        approved_by: Set(None),
+        failure_reason: Set(None),
+        created_at: Set(Utc::now().naive_utc()),
+        approved_at: Set(None),
+        sent_at: Set(None),
+        last_valid_block_height: Set(None),
+    };
+    refund.insert(db).await?;
+
+    Ok(())
+}
+
+/// Builds, signs and sends the refund transfer from the treasury keypair and
+/// records its signature. A transfer that could not be confirmed keeps the
+/// refund `processing` until its blockhash expires. Approving the refund again
+/// then records the transfer if it landed, or signs a new one if it did not,
+/// which also recovers refunds left `processing` by a crash.
+pub async fn send_refund(
+    db: &DatabaseConnection,
+    config: &Config,
+    rpc: &SolanaRpc,
+    refund_id: i32,
+    operator: &str,
+) -> Result<refunds::Model, RefundError> {
+    let refund = match Refunds::find_by_id(refund_id).one(db).await? {
+        Some(refund) => refund,
+        None => return Err(RefundError::NotFound),
+    };
+
+    if refund.status == REFUND_SENT {
+        return Err(RefundError::AlreadySent);
+    }
+
+    // -- Checked against the treasury at startup
+    let keypair = match &config.treasury_keypair {
+        Some(keypair) => keypair,
+        None => return Err(RefundError::Signer("No treasury keypair configured".to_string())),
+    };
+
+    let commitment = match parse_commitment(&config.payment_commitment) {
+        Ok(commitment) => commitment,
+        Err(e) => return Err(RefundError::Chain(e.to_string())),
+    };
+
+    // -- A previous attempt may have landed even though sending reported an
+    // -- error, and may still land until its blockhash expires
+    match previous_attempt(rpc, &refund, commitment).await? {
+        Attempt::Landed(signature) => return mark_sent(db, refund.id, &signature).await,
+        Attempt::Unsettled => return Err(RefundError::InProgress),
+        Attempt::Dropped => (),
+    }
+
+    let (transaction, last_valid_block_height) =
+        build_refund(rpc, keypair, &refund, commitment).await?;
+    let signature = transaction.signatures[0];
+
+    // -- Claim the refund before sending so two approvals cannot both pay out.
+    // -- Matching the previous signature keeps a concurrent approval that
+    // -- already replaced it from being replaced again.
+    let previous_signature = match &refund.signature {
+        Some(signature) => refunds::Column::Signature.eq(signature.as_str()),
+        None => refunds::Column::Signature.is_null(),
+    };
+
+    let claim = Refunds::update_many()
+        .col_expr(refunds::Column::Status, Expr::value(REFUND_PROCESSING))
+        .col_expr(refunds::Column::Signature, Expr::value(signature.to_string()))
+        .col_expr(
+            refunds::Column::LastValidBlockHeight,
+            Expr::value(last_valid_block_height as i64),
+        )
+        .col_expr(refunds::Column::ApprovedBy, Expr::value(operator.to_string()))
+        .col_expr(refunds::Column::ApprovedAt, Expr::value(Utc::now().naive_utc()))
+        .filter(refunds::Column::Id.eq(refund.id))
+        .filter(refunds::Column::Status.ne(REFUND_SENT))
+        .filter(previous_signature)
+        .exec(db)
+        .await?;
+
+    if claim.rows_affected != 1 {
+        return Err(RefundError::InProgress);
+    }
+
+    let sent = rpc.call(move |client| {
+        client.send_transaction(&transaction)?;
+        client.poll_for_signature_with_commitment(&signature, commitment)
+    });
+
+    match sent.await {
+        Ok(_) => mark_sent(db, refund.id, &signature).await,
+        Err(e) => {
+            // -- Not seen yet is not the same as not sent, so the refund stays
+            // -- `processing` until its blockhash expires
+            Refunds::update_many()
+                .col_expr(refunds::Column::FailureReason, Expr::value(e.to_string()))
+                .filter(refunds::Column::Id.eq(refund.id))
+                .exec(db)
+                .await?;
+
+            Err(RefundError::Chain(e.to_string()))
+        }
+    }
+}
+
+/// Settles the last signed transfer of `refund`. The block height is read
+/// before the signature so a transfer that lands in between is still seen.
+async fn previous_attempt(
+    rpc: &SolanaRpc,
+    refund: &refunds::Model,
+    commitment: CommitmentConfig,
+) -> Result<Attempt, RefundError> {
+    let signature = match &refund.signature {
+        Some(signature) => match Signature::from_str(signature) {
+            Ok(signature) => signature,
+            Err(_) => return Err(RefundError::Database("Refund signature is invalid".to_string())),
+        },
+        None => return Ok(Attempt::Dropped),
+    };
+
+    let expired = match refund.last_valid_block_height {
+        Some(last_valid) => {
+            let height =
+                rpc.call(move |client| client.get_block_height_with_commitment(commitment));
+
+            match height.await {
+                Ok(height) => height as i64 > last_valid,
+                Err(e) => return Err(RefundError::Chain(e.to_string())),
+            }
+        }
+        None => {
+            let approved_at = refund.approved_at.unwrap_or(refund.created_at);
+            Utc::now().naive_utc() > approved_at + Duration::minutes(UNRECORDED_EXPIRY_MINUTES)
+        }
+    };
+
+    let status = rpc.call(move |client| {
+        client.get_signature_status_with_commitment_and_history(&signature, commitment, true)
+    });
+
+    match status.await {
+        Ok(Some(Ok(()))) => Ok(Attempt::Landed(signature)),
+        // -- Failed on chain, so it will not be retried by the cluster
+        Ok(Some(Err(_))) => Ok(Attempt::Dropped),
+        Ok(None) if expired => Ok(Attempt::Dropped),
+        Ok(None) => Ok(Attempt::Unsettled),
+        Err(e) => Err(RefundError::Chain(e.to_string())),
+    }
+}
+
+async fn build_refund(
+    rpc: &SolanaRpc,
+    keypair: &Keypair,
+    refund: &refunds::Model,
+    commitment: CommitmentConfig,
+) -> Result<(Transaction, u64), RefundError> {
+    let treasury = keypair.pubkey();
+    let amount = refund.base_amount.max(0) as u64;
+
+    let recipient = match Pubkey::from_str(&refund.account) {
+        Ok(recipient) => recipient,
+        Err(_) => return Err(RefundError::Database("Refund account is not a valid pubkey".to_string())),
+    };
+
+    let currency = match Currency::parse(&refund.currency, refund.decimals) {
+        Ok(currency) => currency,
+        Err(e) => return Err(RefundError::Database(e.to_string())),
+    };
+
+    let mut instructions = match currency {
+        Currency::Sol => vec![system_instruction::transfer(&treasury, &recipient, amount)],
+        Currency::Token { mint, decimals } => {
+            let source = get_associated_token_address(&treasury, &mint);
+            let destination = get_associated_token_address(&recipient, &mint);
+            let mut instructions = Vec::new();
+
+            let account = rpc.call(move |client| client.get_account(&destination));
+
+            if account.await.is_err() {
+                instructions.push(create_associated_token_account(&treasury, &recipient, &mint));
+            }
+
+            let transfer = spl_token::instruction::transfer_checked(
+                &spl_token::id(),
+                &source,
+                &mint,
+                &destination,
+                &treasury,
+                &[],
+                amount,
+                decimals,
+            );
+
+            match transfer {
+                Ok(transfer) => instructions.push(transfer),
+                Err(e) => return Err(RefundError::Chain(e.to_string())),
+            }
+
+            instructions
+        }
+    };
+
+    let memo = format!("{}{}", REFUND_MEMO_PREFIX, refund.id);
+    instructions.push(spl_memo::build_memo(memo.as_bytes(), &[]));
+
+    let latest = rpc.call(move |client| client.get_latest_blockhash_with_commitment(commitment));
+
+    let (blockhash, last_valid_block_height) = match latest.await {
+        Ok(latest) => latest,
+        Err(e) => return Err(RefundError::Chain(e.to_string())),
+    };
+
+    let transaction = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&treasury),
+        &[keypair],
+        blockhash,
+    );
+
+    Ok((transaction, last_valid_block_height))
+}
+
+async fn mark_sent(
+    db: &DatabaseConnection,
+    refund_id: i32,
+    signature: &Signature,
+) -> Result<refunds::Model, RefundError> {
+    Refunds::update_many()
+        .col_expr(refunds::Column::Status, Expr::value(REFUND_SENT))
+        .col_expr(refunds::Column::Signature, Expr::value(signature.to_string()))
+        .col_expr(refunds::Column::SentAt, Expr::value(Utc::now().naive_utc()))
+        .col_expr(
+            refunds::Column::FailureReason,
+            Expr::value(Option::<String>::None),
+        )
+        .filter(refunds::Column::Id.eq(refund_id))
+        .exec(db)
+        .await?;
+
+    let refund = match Refunds::find_by_id(refund_id).one(db).await? {
+        Some(refund) => refund,
+        None => return Err(RefundError::NotFound),
+    };
+
+    mark_refunded(db, refund.payment_id).await?;
+
+    Ok(refund)
+}
+
+/// Moves the refunded payment, and its task if the rank-up failed, to
+/// `refunded`. The transfer has already landed, so a record that moved on in
+/// the meantime is only logged.
+async fn mark_refunded(db: &DatabaseConnection, payment_id: i32) -> Result<(), DbErr> {
+    let payment = match Payments::find_by_id(payment_id).one(db).await? {
+        Some(payment) => payment,
+        None => return Ok(()),
+    };
+
+    if let Err(e) = transition(db, payment.id, payment.status, PaymentStatus::Refunded).await {
+        log::warn!("Payment {} not marked refunded: {}", payment.id, e);
+    }
+
+    if payment.status != PaymentStatus::Failed {
+        return Ok(());
+    }
+
+    if let Some(task) = Tasks::find_by_id(payment.task_id).one(db).await? {
+        if task.status == TaskStatus::Failed {
+            if let Err(e) = transition(db, task.id, task.status, TaskStatus::Refunded).await {
+                log::warn!("Task {} not marked refunded: {}", task.id, e);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,345 @@
+use super::payment::format_amount;
+use super::refund::REFUND_SENT;
+use crate::util::{SysResponse, WebResponse};
+use chrono::{Duration, NaiveDate};
+use entity::history::{self, Entity as History};
+use entity::payments::{self, Entity as Payments, PaymentStatus};
+use entity::refunds::{self, Entity as Refunds};
+use entity::tasks::{self, Entity as Tasks};
+use rocket::http::{ContentType, Header, Status};
+use rocket::serde::{json::Json, Serialize};
+use rocket::Responder;
+use sea_orm::{entity::*, query::*, Condition, DatabaseConnection, DbErr};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Label for tasks quoted before rank and collection were recorded, and for
+/// mints outside a verified collection.
+const UNKNOWN: &str = "unknown";
+
+/// Ids bound per `IN` query, well under SQLite's bound parameter limit.
+const ID_CHUNK: usize = 500;
+
+const CSV_HEADER: &str =
+    "day,collection,rank,outcome,currency,payments,rank_ups,amount,base_amount,signatures,refund_signatures";
+
+/// Report body, either the usual JSON envelope or a CSV download.
+#[derive(Responder)]
+pub enum ReportResponse {
+    Json(WebResponse),
+    Csv(String, ContentType, Header<'static>),
+}
+
+/// What became of a payment whose transaction landed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Confirmed,
+    Refunded,
+    Failed,
+}
+
+impl Outcome {
+    /// Pending payments have not landed and are left out of reports. A payment
+    /// whose refund was sent counts as refunded whatever status it ended in.
+    fn of(status: PaymentStatus, refunded: bool) -> Option<Outcome> {
+        match status {
+            PaymentStatus::Pending => None,
+            _ if refunded => Some(Outcome::Refunded),
+            PaymentStatus::Paid | PaymentStatus::Processing | PaymentStatus::Applied => {
+                Some(Outcome::Confirmed)
+            }
+            PaymentStatus::Refunded => Some(Outcome::Refunded),
+            PaymentStatus::Failed | PaymentStatus::Expired => Some(Outcome::Failed),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Confirmed => "confirmed",
+            Outcome::Refunded => "refunded",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RevenueRow {
+    pub day: NaiveDate,
+    pub collection: String,
+    pub rank: String,
+    pub outcome: Outcome,
+    pub currency: String,
+    pub decimals: i32,
+    pub payments: u64,
+    /// Payments whose rank roll succeeded.
+    pub rank_ups: u64,
+    pub base_amount: i64,
+    pub amount: String,
+    pub signatures: Vec<String>,
+    pub refund_signatures: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct RevenueTotal {
+    pub outcome: Outcome,
+    pub currency: String,
+    pub decimals: i32,
+    pub payments: u64,
+    pub base_amount: i64,
+    pub amount: String,
+}
+
+#[derive(Serialize)]
+pub struct RevenueReport {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub rows: Vec<RevenueRow>,
+    pub totals: Vec<RevenueTotal>,
+}
+
+/// Parses an optional `YYYY-MM-DD` range bound, answering 422 when it is
+/// malformed.
+pub fn parse_day(value: Option<String>) -> Result<Option<NaiveDate>, WebResponse> {
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    match NaiveDate::parse_from_str(&value, "%Y-%m-%d") {
+        Ok(day) => Ok(Some(day)),
+        Err(_) => {
+            let data = json!({ "error": "Dates must be given as YYYY-MM-DD", "value": value });
+            let response = SysResponse { data };
+
+            Err((Status::UnprocessableEntity, Json(response)))
+        }
+    }
+}
+
+/// Totals landed payments by day, collection, rank, outcome and currency. A
+/// payment counts on the day it was confirmed, or created if it never was, and
+/// both ends of the range are inclusive.
+pub async fn revenue_report(
+    db: &DatabaseConnection,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<RevenueReport, DbErr> {
+    let mut query = Payments::find()
+        .filter(payments::Column::Status.ne(PaymentStatus::Pending))
+        .order_by_asc(payments::Column::Id);
+
+    if let Some(from) = from {
+        let start = from.and_hms(0, 0, 0);
+        query = query.filter(
+            Condition::any()
+                .add(payments::Column::ConfirmedAt.gte(start))
+                .add(
+                    Condition::all()
+                        .add(payments::Column::ConfirmedAt.is_null())
+                        .add(payments::Column::CreatedAt.gte(start)),
+                ),
+        );
+    }
+
+    if let Some(to) = to {
+        let end = (to + Duration::days(1)).and_hms(0, 0, 0);
+        query = query.filter(
+            Condition::any()
+                .add(payments::Column::ConfirmedAt.lt(end))
+                .add(
+                    Condition::all()
+                        .add(payments::Column::ConfirmedAt.is_null())
+                        .add(payments::Column::CreatedAt.lt(end)),
+                ),
+        );
+    }
+
+    let payments = query.all(db).await?;
+
+    let task_ids: Vec<i32> = payments
+        .iter()
+        .map(|payment| payment.task_id)
+        .collect::<HashSet<i32>>()
+        .into_iter()
+        .collect();
+    let payment_ids: Vec<i32> = payments.iter().map(|payment| payment.id).collect();
+
+    let mut tasks = HashMap::new();
+    for chunk in task_ids.chunks(ID_CHUNK) {
+        let found = Tasks::find()
+            .filter(tasks::Column::Id.is_in(chunk.to_vec()))
+            .all(db)
+            .await?;
+        tasks.extend(found.into_iter().map(|task| (task.id, task)));
+    }
+
+    let mut rank_ups = HashSet::new();
+    let mut refunds = HashMap::new();
+    for chunk in payment_ids.chunks(ID_CHUNK) {
+        let found = History::find()
+            .filter(history::Column::PaymentId.is_in(chunk.to_vec()))
+            .filter(history::Column::Success.eq(true))
+            .all(db)
+            .await?;
+        rank_ups.extend(found.into_iter().map(|history| history.payment_id));
+
+        let found = Refunds::find()
+            .filter(refunds::Column::PaymentId.is_in(chunk.to_vec()))
+            .filter(refunds::Column::Status.eq(REFUND_SENT))
+            .all(db)
+            .await?;
+        refunds.extend(found.into_iter().map(|refund| (refund.payment_id, refund)));
+    }
+
+    let mut rows: BTreeMap<(NaiveDate, String, String, Outcome, String, i32), RevenueRow> =
+        BTreeMap::new();
+
+    for payment in payments {
+        let refund = refunds.get(&payment.id);
+
+        let outcome = match Outcome::of(payment.status, refund.is_some()) {
+            Some(outcome) => outcome,
+            None => continue,
+        };
+
+        let task = tasks.get(&payment.task_id);
+        let collection = task
+            .and_then(|task| task.collection.clone())
+            .unwrap_or_else(|| UNKNOWN.to_string());
+        let rank = task
+            .and_then(|task| task.rank.clone())
+            .unwrap_or_else(|| UNKNOWN.to_string());
+        let day = payment.confirmed_at.unwrap_or(payment.created_at).date();
+
+        let key = (
+            day,
+            collection.clone(),
+            rank.clone(),
+            outcome,
+            payment.currency.clone(),
+            payment.decimals,
+        );
+
+        let row = rows.entry(key).or_insert_with(|| RevenueRow {
+            day,
+            collection,
+            rank,
+            outcome,
+            currency: payment.currency.clone(),
+            decimals: payment.decimals,
+            payments: 0,
+            rank_ups: 0,
+            base_amount: 0,
+            amount: String::new(),
+            signatures: Vec::new(),
+            refund_signatures: Vec::new(),
+        });
+
+        row.payments += 1;
+        if rank_ups.contains(&payment.id) {
+            row.rank_ups += 1;
+        }
+        row.base_amount += payment.base_amount;
+        row.signatures.push(payment.tx.clone());
+
+        if let Some(signature) = refund.and_then(|refund| refund.signature.clone()) {
+            row.refund_signatures.push(signature);
+        }
+    }
+
+    let mut totals: BTreeMap<(Outcome, String, i32), RevenueTotal> = BTreeMap::new();
+
+    let rows: Vec<RevenueRow> = rows
+        .into_values()
+        .map(|mut row| {
+            row.amount = format_amount(row.base_amount, row.decimals as u8);
+
+            let total = totals
+                .entry((row.outcome, row.currency.clone(), row.decimals))
+                .or_insert_with(|| RevenueTotal {
+                    outcome: row.outcome,
+                    currency: row.currency.clone(),
+                    decimals: row.decimals,
+                    payments: 0,
+                    base_amount: 0,
+                    amount: String::new(),
+                });
+            total.payments += row.payments;
+            total.base_amount += row.base_amount;
+
+            row
+        })
+        .collect();
+
+    let totals = totals
+        .into_values()
+        .map(|mut total| {
+            total.amount = format_amount(total.base_amount, total.decimals as u8);
+            total
+        })
+        .collect();
+
+    Ok(RevenueReport {
+        from,
+        to,
+        rows,
+        totals,
+    })
+}
+
+impl RevenueReport {
+    /// One line per row, followed by the totals with `total` as their day.
+    /// Signatures within a field are separated by spaces.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(CSV_HEADER);
+        csv.push('\n');
+
+        for row in &self.rows {
+            let line = [
+                row.day.to_string(),
+                csv_field(&row.collection),
+                csv_field(&row.rank),
+                row.outcome.name().to_string(),
+                csv_field(&row.currency),
+                row.payments.to_string(),
+                row.rank_ups.to_string(),
+                row.amount.clone(),
+                row.base_amount.to_string(),
+                row.signatures.join(" "),
+                row.refund_signatures.join(" "),
+            ];
+            csv.push_str(&line.join(","));
+            csv.push('\n');
+        }
+
+        for total in &self.totals {
+            let line = [
+                "total".to_string(),
+                String::new(),
+                String::new(),
+                total.outcome.name().to_string(),
+                csv_field(&total.currency),
+                total.payments.to_string(),
+                String::new(),
+                total.amount.clone(),
+                total.base_amount.to_string(),
+                String::new(),
+                String::new(),
+            ];
+            csv.push_str(&line.join(","));
+            csv.push('\n');
+        }
+
+        csv
+    }
+}
+
+/// Quotes a field when it holds a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
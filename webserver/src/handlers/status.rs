@@ -0,0 +1,224 @@
+use crate::util::{SysResponse, WebResponse};
+use chrono::Utc;
+use entity::status::{HistoryStatus, PaymentStatus, TaskStatus};
+use entity::status_transitions::{self, Entity as StatusTransitions};
+use entity::{history, payments, tasks};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::NotSet;
+use sea_orm::{
+    entity::*, query::*, ConnectionTrait, DatabaseConnection, DbErr, Iterable, UpdateMany,
+};
+use serde_json::json;
+use std::fmt;
+
+/// A status column whose changes are checked against its legal transitions
+/// and written to the `status_transitions` log.
+pub trait StatusMachine: ActiveEnum<Value = String> + Copy + fmt::Debug + Send + Sync {
+    type Entity: EntityTrait;
+
+    /// Name the record kind is logged under.
+    const KIND: &'static str;
+
+    fn id_column() -> <Self::Entity as EntityTrait>::Column;
+    fn status_column() -> <Self::Entity as EntityTrait>::Column;
+    fn can_transition_to(self, next: Self) -> bool;
+
+    /// Keeps columns derived from the status in step with it.
+    fn sync(update: UpdateMany<Self::Entity>, _to: Self) -> UpdateMany<Self::Entity> {
+        update
+    }
+}
+
+impl StatusMachine for TaskStatus {
+    type Entity = tasks::Entity;
+    const KIND: &'static str = "task";
+
+    fn id_column() -> tasks::Column {
+        tasks::Column::Id
+    }
+
+    fn status_column() -> tasks::Column {
+        tasks::Column::Status
+    }
+
+    fn can_transition_to(self, next: Self) -> bool {
+        TaskStatus::can_transition_to(self, next)
+    }
+
+    fn sync(update: UpdateMany<tasks::Entity>, to: Self) -> UpdateMany<tasks::Entity> {
+        update.col_expr(
+            tasks::Column::Success,
+            Expr::value(to == TaskStatus::Applied),
+        )
+    }
+}
+
+impl StatusMachine for PaymentStatus {
+    type Entity = payments::Entity;
+    const KIND: &'static str = "payment";
+
+    fn id_column() -> payments::Column {
+        payments::Column::Id
+    }
+
+    fn status_column() -> payments::Column {
+        payments::Column::Status
+    }
+
+    fn can_transition_to(self, next: Self) -> bool {
+        PaymentStatus::can_transition_to(self, next)
+    }
+
+    fn sync(update: UpdateMany<payments::Entity>, to: Self) -> UpdateMany<payments::Entity> {
+        update.col_expr(payments::Column::Success, Expr::value(to.is_paid()))
+    }
+}
+
+impl StatusMachine for HistoryStatus {
+    type Entity = history::Entity;
+    const KIND: &'static str = "history";
+
+    fn id_column() -> history::Column {
+        history::Column::Id
+    }
+
+    fn status_column() -> history::Column {
+        history::Column::Status
+    }
+
+    fn can_transition_to(self, next: Self) -> bool {
+        HistoryStatus::can_transition_to(self, next)
+    }
+}
+
+#[derive(Debug)]
+pub enum TransitionError {
+    /// The state machine does not allow this move.
+    Illegal(String, String),
+    /// The record was no longer in the expected status.
+    Stale,
+    Database(DbErr),
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionError::Illegal(from, to) => {
+                write!(f, "Cannot move from {} to {}", from, to)
+            }
+            TransitionError::Stale => write!(f, "Record status has already changed"),
+            TransitionError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<DbErr> for TransitionError {
+    fn from(e: DbErr) -> Self {
+        TransitionError::Database(e)
+    }
+}
+
+/// Moves a record from `from` to `to`, failing if the move is illegal or the
+/// record is no longer in `from`.
+pub async fn transition<S: StatusMachine, C: ConnectionTrait>(
+    db: &C,
+    id: i32,
+    from: S,
+    to: S,
+) -> Result<(), TransitionError> {
+    transition_with(db, id, from, to, |update| update).await
+}
+
+/// Like `transition`, with extra columns written in the same statement so they
+/// only change together with the status.
+pub async fn transition_with<S, C, F>(
+    db: &C,
+    id: i32,
+    from: S,
+    to: S,
+    extra: F,
+) -> Result<(), TransitionError>
+where
+    S: StatusMachine,
+    C: ConnectionTrait,
+    F: FnOnce(UpdateMany<S::Entity>) -> UpdateMany<S::Entity>,
+{
+    if !from.can_transition_to(to) {
+        return Err(TransitionError::Illegal(from.to_value(), to.to_value()));
+    }
+
+    let update = S::Entity::update_many()
+        .col_expr(S::status_column(), Expr::value(to.to_value()))
+        .filter(S::id_column().eq(id))
+        .filter(S::status_column().eq(from.to_value()));
+
+    let result = extra(S::sync(update, to)).exec(db).await?;
+
+    if result.rows_affected != 1 {
+        return Err(TransitionError::Stale);
+    }
+
+    record_transition(db, id, Some(from), to).await?;
+
+    Ok(())
+}
+
+/// Logs a status change. Newly created records are logged with no previous
+/// status.
+pub async fn record_transition<S: StatusMachine, C: ConnectionTrait>(
+    db: &C,
+    id: i32,
+    from: Option<S>,
+    to: S,
+) -> Result<(), DbErr> {
+    let transition = status_transitions::ActiveModel {
+        id: NotSet,
+        kind: Set(S::KIND.to_string()),
+        record_id: Set(id),
+        from_status: Set(from.map(|from| from.to_value())),
+        to_status: Set(to.to_value()),
+        created_at: Set(Utc::now().naive_utc()),
+    };
+    transition.insert(db).await?;
+
+    Ok(())
+}
+
+/// Status history of a record, oldest first.
+pub async fn transitions_for(
+    db: &DatabaseConnection,
+    kind: &str,
+    id: i32,
+) -> Result<Vec<status_transitions::Model>, DbErr> {
+    StatusTransitions::find()
+        .filter(status_transitions::Column::Kind.eq(kind))
+        .filter(status_transitions::Column::RecordId.eq(id))
+        .order_by_asc(status_transitions::Column::Id)
+        .all(db)
+        .await
+}
+
+/// Parses an optional `?status=` filter, answering 422 with the accepted values
+/// when it names no status of `S`.
+pub fn status_filter<S>(status: Option<String>) -> Result<Option<S>, WebResponse>
+where
+    S: StatusMachine + Iterable,
+{
+    let status = match status {
+        Some(status) => status,
+        None => return Ok(None),
+    };
+
+    match S::try_from_value(&status) {
+        Ok(status) => Ok(Some(status)),
+        Err(_) => {
+            let accepted: Vec<String> = S::iter().map(|status| status.to_value()).collect();
+            let data = json!({ "error": "Unknown status", "value": status, "accepted": accepted });
+            let response = SysResponse { data };
+
+            Err((Status::UnprocessableEntity, Json(response)))
+        }
+    }
+}
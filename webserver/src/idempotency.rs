@@ -0,0 +1,240 @@
+use crate::util::{is_unique_violation, SysResponse, WebResponse};
+use chrono::{Duration, Utc};
+use entity::idempotency_keys::{self, Entity as IdempotencyKeys};
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+    serde::{json::Json, Serialize},
+};
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::NotSet;
+use sea_orm::{entity::*, query::*, DatabaseConnection};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+
+/// Longest `Idempotency-Key` header accepted.
+const MAX_KEY_LENGTH: usize = 255;
+
+/// How long a claim without a stored response holds its key. A claim left
+/// behind by a crash or a dropped request can be taken over after this.
+const IN_FLIGHT_LEASE_SECONDS: i64 = 300;
+
+/// How often a running request extends its claim's lease.
+const LEASE_RENEW_SECONDS: u64 = 60;
+
+/// Request guard reading the optional `Idempotency-Key` header.
+pub struct IdempotencyKey(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IdempotencyKey {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.headers().get_one("Idempotency-Key") {
+            None => Outcome::Success(IdempotencyKey(None)),
+            Some(key) if key.is_empty() || key.len() > MAX_KEY_LENGTH => {
+                Outcome::Failure((Status::UnprocessableEntity, ()))
+            }
+            Some(key) => Outcome::Success(IdempotencyKey(Some(key.to_string()))),
+        }
+    }
+}
+
+/// Runs `handler` once per key, scope and account. Retries with the same key
+/// and body get the stored response back, and a different body gets a 422.
+/// Server errors are not stored so the client can retry them. Until the
+/// response is stored the key is held on a lease of `IN_FLIGHT_LEASE_SECONDS`,
+/// renewed for as long as the handler runs.
+pub async fn once<T, F>(
+    db: &DatabaseConnection,
+    key: &IdempotencyKey,
+    scope: &str,
+    account: &str,
+    request: &T,
+    ttl_hours: i64,
+    handler: F,
+) -> WebResponse
+where
+    T: Serialize,
+    F: Future<Output = WebResponse>,
+{
+    let key = match &key.0 {
+        Some(key) => key.as_str(),
+        None => return handler.await,
+    };
+
+    let request_hash = match serde_json::to_vec(request) {
+        Ok(body) => hex::encode(Sha256::digest(&body)),
+        Err(e) => return error(Status::InternalServerError, &e.to_string()),
+    };
+
+    let now = Utc::now().naive_utc();
+
+    // -- Expired keys and stale in-flight claims are dropped so the key can be
+    // -- used again
+    let cleared = IdempotencyKeys::delete_many()
+        .filter(idempotency_keys::Column::Scope.eq(scope))
+        .filter(idempotency_keys::Column::Account.eq(account))
+        .filter(idempotency_keys::Column::Key.eq(key))
+        .filter(idempotency_keys::Column::ExpiresAt.lt(now))
+        .exec(db)
+        .await;
+
+    if cleared.is_err() {
+        return error(
+            Status::InternalServerError,
+            "Failed to check Idempotency-Key",
+        );
+    }
+
+    let claim = idempotency_keys::ActiveModel {
+        id: NotSet,
+        key: Set(key.to_string()),
+        scope: Set(scope.to_string()),
+        account: Set(account.to_string()),
+        request_hash: Set(request_hash.clone()),
+        status_code: Set(None),
+        response: Set(None),
+        created_at: Set(now),
+        expires_at: Set(now + Duration::seconds(IN_FLIGHT_LEASE_SECONDS)),
+    };
+
+    let claimed = match claim.insert(db).await {
+        Ok(claimed) => claimed,
+        Err(e) if is_unique_violation(&e) => {
+            return replay(db, key, scope, account, &request_hash).await
+        }
+        Err(_) => {
+            return error(
+                Status::InternalServerError,
+                "Failed to store Idempotency-Key",
+            )
+        }
+    };
+
+    tokio::pin!(handler);
+    let mut renew = tokio::time::interval(std::time::Duration::from_secs(LEASE_RENEW_SECONDS));
+    renew.tick().await;
+
+    let (status, Json(response)) = loop {
+        tokio::select! {
+            response = &mut handler => break response,
+            _ = renew.tick() => renew_lease(db, claimed.id).await,
+        }
+    };
+
+    if status.code >= 500 {
+        let _ = IdempotencyKeys::delete_many()
+            .filter(idempotency_keys::Column::Id.eq(claimed.id))
+            .exec(db)
+            .await;
+    } else {
+        // -- Only the claim this request still holds takes the response
+        let stored = IdempotencyKeys::update_many()
+            .col_expr(
+                idempotency_keys::Column::StatusCode,
+                Expr::value(status.code as i32),
+            )
+            .col_expr(
+                idempotency_keys::Column::Response,
+                Expr::value(response.data.to_string()),
+            )
+            .col_expr(
+                idempotency_keys::Column::ExpiresAt,
+                Expr::value(Utc::now().naive_utc() + Duration::hours(ttl_hours)),
+            )
+            .filter(idempotency_keys::Column::Id.eq(claimed.id))
+            .filter(idempotency_keys::Column::StatusCode.is_null())
+            .exec(db)
+            .await;
+
+        match stored {
+            Ok(stored) if stored.rows_affected == 1 => (),
+            Ok(_) => log::warn!(
+                "Idempotency-Key claim {} was lost before its response was stored",
+                claimed.id
+            ),
+            Err(e) => log::warn!("Failed to store response for Idempotency-Key: {}", e),
+        }
+    }
+
+    (status, Json(response))
+}
+
+/// Pushes back the lease of a claim that is still waiting for its response.
+async fn renew_lease(db: &DatabaseConnection, claim_id: i32) {
+    let expires_at = Utc::now().naive_utc() + Duration::seconds(IN_FLIGHT_LEASE_SECONDS);
+
+    let renewed = IdempotencyKeys::update_many()
+        .col_expr(idempotency_keys::Column::ExpiresAt, Expr::value(expires_at))
+        .filter(idempotency_keys::Column::Id.eq(claim_id))
+        .filter(idempotency_keys::Column::StatusCode.is_null())
+        .exec(db)
+        .await;
+
+    if let Err(e) = renewed {
+        log::warn!("Failed to renew Idempotency-Key claim {}: {}", claim_id, e);
+    }
+}
+
+async fn replay(
+    db: &DatabaseConnection,
+    key: &str,
+    scope: &str,
+    account: &str,
+    request_hash: &str,
+) -> WebResponse {
+    let fetch = IdempotencyKeys::find()
+        .filter(idempotency_keys::Column::Scope.eq(scope))
+        .filter(idempotency_keys::Column::Account.eq(account))
+        .filter(idempotency_keys::Column::Key.eq(key))
+        .one(db)
+        .await;
+
+    let stored = match fetch {
+        Ok(Some(stored)) => stored,
+        Ok(None) => {
+            return error(
+                Status::Conflict,
+                "Request with this Idempotency-Key was retried, try again",
+            )
+        }
+        Err(_) => {
+            return error(
+                Status::InternalServerError,
+                "Failed to check Idempotency-Key",
+            )
+        }
+    };
+
+    if stored.request_hash != request_hash {
+        return error(
+            Status::UnprocessableEntity,
+            "Idempotency-Key was already used with a different request",
+        );
+    }
+
+    let (code, body) = match (stored.status_code, stored.response) {
+        (Some(code), Some(body)) => (code, body),
+        _ => {
+            return error(
+                Status::Conflict,
+                "Request with this Idempotency-Key is still in progress",
+            )
+        }
+    };
+
+    let status = Status::from_code(code as u16).unwrap_or(Status::Ok);
+    let data = serde_json::from_str(&body).unwrap_or(serde_json::Value::Null);
+    let response = SysResponse { data };
+
+    (status, Json(response))
+}
+
+fn error(status: Status, message: &str) -> WebResponse {
+    let data = json!({ "error": message });
+    let response = SysResponse { data };
+
+    (status, Json(response))
+}
@@ -0,0 +1,353 @@
+use crate::handlers::metadata::RankUp;
+use crate::handlers::payment::{fail_rank_up, run_rank_up};
+use crate::rpc::SolanaRpc;
+use crate::Config;
+use chrono::{Duration as ChronoDuration, NaiveDateTime, Utc};
+use entity::history;
+use entity::jobs::{self, Entity as Jobs};
+use rocket::serde::Deserialize;
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::NotSet;
+use sea_orm::{
+    entity::*, query::*, Condition, ConnectionTrait, DatabaseConnection, DbErr, TransactionTrait,
+    UpdateMany,
+};
+use std::{sync::Arc, time::Duration};
+
+pub const JOB_QUEUED: &str = "queued";
+pub const JOB_RUNNING: &str = "running";
+pub const JOB_DONE: &str = "done";
+pub const JOB_DEAD: &str = "dead";
+
+/// `[default.jobs]` section of `Rocket.toml`.
+#[derive(Deserialize)]
+pub struct JobsConfig {
+    pub workers: usize,
+    pub poll_interval_seconds: u64,
+    /// Attempts before a job is dead-lettered and its payment owed back.
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled for every retry after it.
+    pub backoff_seconds: i64,
+    /// How long a claimed job is hidden from other workers. The job of a
+    /// worker that died is picked up again once this runs out.
+    pub visibility_timeout_seconds: i64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        JobsConfig {
+            workers: 4,
+            poll_interval_seconds: 2,
+            max_attempts: 5,
+            backoff_seconds: 30,
+            visibility_timeout_seconds: 900,
+        }
+    }
+}
+
+/// Queues the rank-up for a confirmed payment's history row. Runs in the
+/// caller's transaction so a history row never exists without its job.
+pub async fn enqueue<C: ConnectionTrait>(
+    db: &C,
+    history: &history::Model,
+) -> Result<jobs::Model, DbErr> {
+    let now = Utc::now().naive_utc();
+
+    let job = jobs::ActiveModel {
+        id: NotSet,
+        history_id: Set(history.id),
+        payment_id: Set(history.payment_id),
+        task_id: Set(history.task_id),
+        account: Set(history.account.clone()),
+        status: Set(JOB_QUEUED.to_string()),
+        attempts: Set(0),
+        run_at: Set(now),
+        locked_until: Set(None),
+        last_error: Set(None),
+        success: Set(None),
+        metadata_uri: Set(None),
+        created_at: Set(now),
+        finished_at: Set(None),
+    };
+
+    job.insert(db).await
+}
+
+/// Keeps the published metadata of a job so retries set the same URI on chain
+/// instead of rolling again. Fails if the worker's lock ran out, since another
+/// worker may have rolled the job again by then.
+pub async fn record_prepared(
+    db: &DatabaseConnection,
+    job: &jobs::Model,
+    rank_up: &RankUp,
+) -> Result<(), DbErr> {
+    let recorded = fenced(job)
+        .filter(jobs::Column::LockedUntil.gt(Utc::now().naive_utc()))
+        .col_expr(jobs::Column::Success, Expr::value(rank_up.successful))
+        .col_expr(jobs::Column::MetadataUri, Expr::value(rank_up.uri.clone()))
+        .exec(db)
+        .await?;
+
+    if recorded.rows_affected != 1 {
+        return Err(lock_lost(job));
+    }
+
+    Ok(())
+}
+
+/// Checks that the worker still holds the job's lock and extends it, so no
+/// other worker can claim the job while the next step runs.
+pub async fn hold(
+    db: &DatabaseConnection,
+    job: &mut jobs::Model,
+    config: &JobsConfig,
+) -> Result<(), DbErr> {
+    let now = Utc::now().naive_utc();
+    let locked_until = now + ChronoDuration::seconds(config.visibility_timeout_seconds);
+
+    let held = fenced(job)
+        .filter(jobs::Column::LockedUntil.gt(now))
+        .col_expr(jobs::Column::LockedUntil, Expr::value(locked_until))
+        .exec(db)
+        .await?;
+
+    if held.rows_affected != 1 {
+        return Err(lock_lost(job));
+    }
+
+    job.locked_until = Some(locked_until);
+
+    Ok(())
+}
+
+/// Takes due jobs off the queue one at a time for as long as the server runs.
+/// Started once per configured worker. Each job runs in its own task, so a
+/// rank-up that panics fails its attempt instead of stopping the worker.
+pub async fn run_worker(db: DatabaseConnection, config: Arc<Config>, rpc: SolanaRpc) {
+    let idle = Duration::from_secs(config.jobs.poll_interval_seconds);
+
+    loop {
+        match claim(&db, &config.jobs).await {
+            Ok(Some(job)) => {
+                let claimed = job.clone();
+                let (task_db, task_config, task_rpc) = (db.clone(), config.clone(), rpc.clone());
+                let running =
+                    tokio::spawn(async move { work(&task_db, &task_config, &task_rpc, job).await });
+
+                if let Err(e) = running.await {
+                    recover(&db, &config, &claimed, &e.to_string()).await;
+                }
+            }
+            Ok(None) => tokio::time::sleep(idle).await,
+            Err(e) => {
+                log::warn!("Job worker: {}", e);
+                tokio::time::sleep(idle).await;
+            }
+        }
+    }
+}
+
+/// Claims the oldest job that is due, or whose worker's lock ran out, by
+/// locking it and counting the attempt. Returns `None` when there is no such
+/// job or another worker claimed it first.
+async fn claim(db: &DatabaseConnection, config: &JobsConfig) -> Result<Option<jobs::Model>, DbErr> {
+    let now = Utc::now().naive_utc();
+
+    let runnable = Condition::any()
+        .add(
+            Condition::all()
+                .add(jobs::Column::Status.eq(JOB_QUEUED))
+                .add(jobs::Column::RunAt.lte(now)),
+        )
+        .add(
+            Condition::all()
+                .add(jobs::Column::Status.eq(JOB_RUNNING))
+                .add(jobs::Column::LockedUntil.lt(now)),
+        );
+
+    let job = Jobs::find()
+        .filter(runnable.clone())
+        .order_by_asc(jobs::Column::RunAt)
+        .one(db)
+        .await?;
+
+    let job = match job {
+        Some(job) => job,
+        None => return Ok(None),
+    };
+
+    let locked_until = now + ChronoDuration::seconds(config.visibility_timeout_seconds);
+
+    let claim = Jobs::update_many()
+        .col_expr(jobs::Column::Status, Expr::value(JOB_RUNNING))
+        .col_expr(jobs::Column::LockedUntil, Expr::value(locked_until))
+        .col_expr(
+            jobs::Column::Attempts,
+            Expr::col(jobs::Column::Attempts).add(1),
+        )
+        .filter(jobs::Column::Id.eq(job.id))
+        .filter(runnable)
+        .exec(db)
+        .await?;
+
+    if claim.rows_affected != 1 {
+        return Ok(None);
+    }
+
+    Ok(Some(jobs::Model {
+        status: JOB_RUNNING.to_string(),
+        attempts: job.attempts + 1,
+        locked_until: Some(locked_until),
+        ..job
+    }))
+}
+
+async fn work(db: &DatabaseConnection, config: &Config, rpc: &SolanaRpc, mut job: jobs::Model) {
+    // -- Workers that died mid-job still used up their attempt
+    if job.attempts > config.jobs.max_attempts {
+        let reason = job
+            .last_error
+            .clone()
+            .unwrap_or_else(|| "Job did not finish in time".to_string());
+
+        return dead_letter(db, &job, &reason).await;
+    }
+
+    let rank_up = match run_rank_up(db, config, rpc, &mut job).await {
+        Ok(rank_up) => rank_up,
+        Err(e) => return fail_attempt(db, config, &job, &e.to_string()).await,
+    };
+
+    log::info!(
+        "Job {}: rank-up applied (success: {})",
+        job.id,
+        rank_up.successful
+    );
+
+    let result = fenced(&job)
+        .col_expr(jobs::Column::Status, Expr::value(JOB_DONE))
+        .col_expr(
+            jobs::Column::LockedUntil,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .col_expr(
+            jobs::Column::FinishedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .exec(db)
+        .await;
+
+    if let Err(e) = result {
+        log::warn!("Job {}: {}", job.id, e);
+    }
+}
+
+/// Fails the attempt of a job whose task panicked. The task may have extended
+/// the lock, so the job is read back and only failed if no other worker has
+/// claimed it since.
+async fn recover(db: &DatabaseConnection, config: &Config, claimed: &jobs::Model, reason: &str) {
+    let job = match Jobs::find_by_id(claimed.id).one(db).await {
+        Ok(Some(job)) if job.status == JOB_RUNNING && job.attempts == claimed.attempts => job,
+        Ok(_) => return,
+        Err(e) => return log::warn!("Job {}: {}", claimed.id, e),
+    };
+
+    fail_attempt(db, config, &job, reason).await
+}
+
+/// Queues a failed job again after a backoff, or dead-letters it once it is out
+/// of attempts.
+async fn fail_attempt(db: &DatabaseConnection, config: &Config, job: &jobs::Model, reason: &str) {
+    if job.attempts >= config.jobs.max_attempts {
+        return dead_letter(db, job, reason).await;
+    }
+
+    let retries = (job.attempts - 1).clamp(0, 16) as u32;
+    let delay = config
+        .jobs
+        .backoff_seconds
+        .saturating_mul(2i64.pow(retries));
+    let run_at = Utc::now().naive_utc() + ChronoDuration::seconds(delay);
+
+    log::warn!(
+        "Job {}: attempt {} failed: {}",
+        job.id,
+        job.attempts,
+        reason
+    );
+
+    let result = fenced(job)
+        .col_expr(jobs::Column::Status, Expr::value(JOB_QUEUED))
+        .col_expr(jobs::Column::RunAt, Expr::value(run_at))
+        .col_expr(
+            jobs::Column::LockedUntil,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .col_expr(jobs::Column::LastError, Expr::value(reason.to_string()))
+        .exec(db)
+        .await;
+
+    if let Err(e) = result {
+        log::warn!("Job {}: {}", job.id, e);
+    }
+}
+
+/// Stops retrying a job and fails its rank-up, which owes the payment back.
+/// Both commit together; if either fails the job keeps its lock and is
+/// dead-lettered again once the lock runs out.
+async fn dead_letter(db: &DatabaseConnection, job: &jobs::Model, reason: &str) {
+    log::warn!(
+        "Job {}: dead-lettered after {} attempts: {}",
+        job.id,
+        job.attempts,
+        reason
+    );
+
+    if let Err(e) = fail_job(db, job, reason).await {
+        log::warn!("Job {}: failed to record the failed rank-up: {}", job.id, e);
+    }
+}
+
+async fn fail_job(db: &DatabaseConnection, job: &jobs::Model, reason: &str) -> anyhow::Result<()> {
+    let txn = db.begin().await?;
+
+    let update = fenced(job)
+        .col_expr(jobs::Column::Status, Expr::value(JOB_DEAD))
+        .col_expr(
+            jobs::Column::LockedUntil,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .col_expr(jobs::Column::LastError, Expr::value(reason.to_string()))
+        .col_expr(
+            jobs::Column::FinishedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .exec(&txn)
+        .await?;
+
+    // -- Another worker took the job over
+    if update.rows_affected != 1 {
+        return Ok(());
+    }
+
+    fail_rank_up(&txn, job, reason).await?;
+    txn.commit().await?;
+
+    Ok(())
+}
+
+fn lock_lost(job: &jobs::Model) -> DbErr {
+    DbErr::Custom(format!(
+        "Job {} lock was taken over by another worker",
+        job.id
+    ))
+}
+
+/// Update of a claimed job that only applies while this worker still holds
+/// its lock.
+fn fenced(job: &jobs::Model) -> UpdateMany<Jobs> {
+    Jobs::update_many()
+        .filter(jobs::Column::Id.eq(job.id))
+        .filter(jobs::Column::Status.eq(JOB_RUNNING))
+        .filter(jobs::Column::LockedUntil.eq(job.locked_until))
+}
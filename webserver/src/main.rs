@@ -1,28 +1,54 @@
-use chrono::Utc;
-use handlers::metadata::handle_update;
+use anyhow::anyhow;
+use chrono::{Duration, Utc};
+use handlers::auth::{
+    consume_nonce, find_nonce, is_token_revoked, issue_nonce, issue_refresh_token,
+    revoke_access_token, revoke_refresh_tokens, rotate_refresh_token,
+};
+use handlers::payment::{
+    check_price, find_reference, process_payment, quote_valid, Currency, CurrencyConfig,
+    TransferRequest, PAYMENT_MEMO_PREFIX,
+};
+use handlers::refund::{send_refund, REFUND_STATUSES};
+use handlers::report::{parse_day, revenue_report, ReportResponse};
+use handlers::status::{
+    record_transition, status_filter, transition, transitions_for, StatusMachine, TransitionError,
+};
 use migration::MigratorTrait;
 use rocket::{
+    data::{self, Data, FromData, ToByteUnit},
     fairing::{self, AdHoc},
-    http::Status,
+    http::{ContentType, Status},
     request::{FromRequest, Outcome, Request},
-    serde::{json::Json, Deserialize},
+    serde::{
+        json::{Error as JsonError, Json},
+        Deserialize,
+    },
     Build, Rocket, State,
 };
+use serde::de::DeserializeOwned;
 use serde_json::json;
+use solana_sdk::signature::{Keypair, Signer};
+use std::sync::Arc;
 
 mod util;
-use util::PaymentReceive;
+use util::jwt::{JwtKeyConfig, JwtKeys};
+use util::params::{body_error, invalid_field, Base58Pubkey, InvalidField, InvalidParam};
+use util::siws::{SiwsConfig, SiwsMessage};
 use util::{
-    create_jwt, crypto, ApiKey, ApiKeyError, AuthRequest, PaymentCreate, SysResponse, TaskCreate,
-    WebResponse,
+    create_jwt, crypto, AdminKey, ApiKey, ApiKeyError, AuthRequest, OperatorKey, PaymentCreate,
+    RefreshRequest, Role, SignInProof, SysResponse, TaskCreate, WebResponse,
 };
+use util::{IntegrationCreate, PaymentReceive, SignedPayload, WebhookError};
 
 use entity::accounts::Entity as Accounts;
-use entity::history::Entity as History;
-use entity::payments::Entity as Payments;
-use entity::tasks::Entity as Tasks;
-
-use sea_orm::entity::prelude::Uuid;
+use entity::history::{Entity as History, HistoryStatus};
+use entity::integrations::Entity as Integrations;
+use entity::jobs::Entity as Jobs;
+use entity::payments::{Entity as Payments, PaymentStatus};
+use entity::refunds::Entity as Refunds;
+use entity::tasks::{Entity as Tasks, TaskStatus};
+
+use sea_orm::sea_query::Expr;
 use sea_orm::ActiveValue::NotSet;
 use sea_orm::{entity::*, query::*};
 use sea_orm_rocket::{Connection, Database};
@@ -31,8 +57,6 @@ use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::Header;
 use rocket::Response;
 
-use solana_sdk::signature::Signature;
-
 pub struct CORS;
 
 #[rocket::async_trait]
@@ -58,15 +82,117 @@ impl Fairing for CORS {
 mod handlers;
 mod models;
 
+mod idempotency;
+use idempotency::IdempotencyKey;
+
+mod jobs;
+use jobs::JobsConfig;
+
 mod pool;
 use pool::Db;
 
+mod rate_limit;
+use rate_limit::{
+    AuthRoutes, PaymentRoutes, RateLimit, RateLimitConfig, RateLimiter, RetryAfter, TaskRoutes,
+};
+
+mod rpc;
+use rpc::{RpcConfig, SolanaRpc};
+
+mod secrets;
+use secrets::SecretKeypair;
+
+mod storage;
+use storage::StorageSettings;
+
+mod watcher;
+use watcher::WatcherConfig;
+
 #[macro_use]
 extern crate rocket;
 
 #[derive(Deserialize)]
 pub struct Config {
-    pub jwt_secret: String,
+    pub jwt_signing_kid: String,
+    pub jwt_keys: Vec<JwtKeyConfig>,
+    #[serde(default = "default_access_token_ttl")]
+    pub access_token_ttl_minutes: i64,
+    #[serde(default = "default_refresh_token_ttl")]
+    pub refresh_token_ttl_days: i64,
+    #[serde(default = "default_nonce_ttl")]
+    pub nonce_ttl_seconds: i64,
+    pub siws: SiwsConfig,
+    #[serde(default = "default_webhook_tolerance")]
+    pub webhook_tolerance_seconds: i64,
+    #[serde(default)]
+    pub admin_pubkeys: Vec<String>,
+    #[serde(default)]
+    pub operator_pubkeys: Vec<String>,
+    pub treasury: Base58Pubkey,
+    pub treasury_keypair: Option<SecretKeypair>,
+    pub update_authority: SecretKeypair,
+    pub collection_mint: Base58Pubkey,
+    #[serde(default)]
+    pub currency: CurrencyConfig,
+    #[serde(default = "default_payment_commitment")]
+    pub payment_commitment: String,
+    #[serde(default = "default_solana_pay_label")]
+    pub solana_pay_label: String,
+    #[serde(default = "default_quote_ttl")]
+    pub quote_ttl_minutes: i64,
+    #[serde(default = "default_idempotency_ttl")]
+    pub idempotency_ttl_hours: i64,
+    #[serde(default)]
+    pub watcher: WatcherConfig,
+    #[serde(default)]
+    pub rpc: RpcConfig,
+    #[serde(default)]
+    pub jobs: JobsConfig,
+    pub storage: StorageSettings,
+}
+
+impl Config {
+    pub fn role_for(&self, pubkey: &str) -> Role {
+        if self.admin_pubkeys.iter().any(|admin| admin == pubkey) {
+            Role::Admin
+        } else if self.operator_pubkeys.iter().any(|operator| operator == pubkey) {
+            Role::Operator
+        } else {
+            Role::User
+        }
+    }
+}
+
+fn default_access_token_ttl() -> i64 {
+    30
+}
+
+fn default_refresh_token_ttl() -> i64 {
+    30
+}
+
+fn default_nonce_ttl() -> i64 {
+    300
+}
+
+fn default_webhook_tolerance() -> i64 {
+    300
+}
+
+fn default_payment_commitment() -> String {
+    "finalized".to_string()
+}
+
+fn default_solana_pay_label() -> String {
+    "Gibki Metamutate".to_string()
+}
+
+fn default_quote_ttl() -> i64 {
+    15
+}
+
+fn default_idempotency_ttl() -> i64 {
+    24
 }
 
 #[rocket::async_trait]
@@ -75,16 +201,145 @@ impl<'r> FromRequest<'r> for ApiKey<'r> {
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
         // Retrieve the config state like this
+        let jwt_keys = req.rocket().state::<JwtKeys>().unwrap();
+        let db = &Db::fetch(req.rocket()).unwrap().conn;
+
+        let key = match req.headers().get_one("Authorization") {
+            Some(key) => key,
+            None => return Outcome::Failure((Status::Unauthorized, ApiKeyError::Missing)),
+        };
+
+        let claims = match util::decode_jwt(key, jwt_keys) {
+            Ok(claims) => claims,
+            Err(_) => return Outcome::Failure((Status::Unauthorized, ApiKeyError::Invalid)),
+        };
+
+        // -- Consult the revocation store for tokens that were logged out
+        match is_token_revoked(db, &claims.jti).await {
+            Ok(false) => Outcome::Success(ApiKey { token: key, claims }),
+            _ => Outcome::Failure((Status::Unauthorized, ApiKeyError::Revoked)),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for OperatorKey<'r> {
+    type Error = ApiKeyError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let key = try_outcome!(req.guard::<ApiKey<'r>>().await);
+
+        if key.claims.role.is_operator() {
+            Outcome::Success(OperatorKey(key))
+        } else {
+            Outcome::Failure((Status::Forbidden, ApiKeyError::Forbidden))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminKey<'r> {
+    type Error = ApiKeyError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let key = try_outcome!(req.guard::<ApiKey<'r>>().await);
+
+        if key.claims.role.is_admin() {
+            Outcome::Success(AdminKey(key))
+        } else {
+            Outcome::Failure((Status::Forbidden, ApiKeyError::Forbidden))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for SignedPayload<T> {
+    type Error = WebhookError;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
         let config = req.rocket().state::<Config>().unwrap();
+        let db = &Db::fetch(req.rocket()).unwrap().conn;
+
+        let headers = req.headers();
+        let (name, timestamp, signature) = match (
+            headers.get_one("X-Integration"),
+            headers.get_one("X-Timestamp"),
+            headers.get_one("X-Signature"),
+        ) {
+            (Some(name), Some(timestamp), Some(signature)) => (name, timestamp, signature),
+            _ => {
+                return rocket::outcome::Outcome::Failure((
+                    Status::Unauthorized,
+                    WebhookError::Missing,
+                ))
+            }
+        };
 
-        fn is_valid(key: &str, secret: &str) -> bool {
-            matches!(util::decode_jwt(key, secret), Ok(_))
+        // -- Reject stale deliveries so a captured request cannot be replayed later
+        let within_tolerance = match timestamp.parse::<i64>() {
+            Ok(sent_at) => {
+                (Utc::now().timestamp() - sent_at).abs() <= config.webhook_tolerance_seconds
+            }
+            Err(_) => false,
+        };
+
+        if !within_tolerance {
+            return rocket::outcome::Outcome::Failure((
+                Status::Unauthorized,
+                WebhookError::Expired,
+            ));
+        }
+
+        let fetch_integration = Integrations::find()
+            .filter(entity::integrations::Column::Name.eq(name))
+            .filter(entity::integrations::Column::Active.eq(true))
+            .one(db)
+            .await;
+
+        let integration = match fetch_integration {
+            Ok(Some(integration)) => integration,
+            _ => {
+                return rocket::outcome::Outcome::Failure((
+                    Status::Unauthorized,
+                    WebhookError::Invalid,
+                ))
+            }
+        };
+
+        let limit = req.limits().get("json").unwrap_or_else(|| 1.mebibytes());
+        let body = match data.open(limit).into_bytes().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            _ => {
+                return rocket::outcome::Outcome::Failure((
+                    Status::PayloadTooLarge,
+                    WebhookError::Malformed,
+                ))
+            }
+        };
+
+        if crypto::verify_webhook_signature(&integration.secret, timestamp, &body, signature)
+            .is_err()
+        {
+            return rocket::outcome::Outcome::Failure((
+                Status::Unauthorized,
+                WebhookError::Invalid,
+            ));
         }
 
-        match req.headers().get_one("Authorization") {
-            None => Outcome::Failure((Status::Unauthorized, ApiKeyError::Missing)),
-            Some(key) if is_valid(key, &config.jwt_secret) => Outcome::Success(ApiKey(key)),
-            Some(_) => Outcome::Failure((Status::Unauthorized, ApiKeyError::Invalid)),
+        match serde_json::from_slice::<T>(&body) {
+            Ok(payload) => rocket::outcome::Outcome::Success(SignedPayload {
+                integration: integration.name,
+                payload,
+            }),
+            Err(e) => {
+                // -- Kept for the 422 catcher, which cannot see the guard's error
+                req.local_cache(|| invalid_field(&body, &e));
+
+                rocket::outcome::Outcome::Failure((
+                    Status::UnprocessableEntity,
+                    WebhookError::Malformed,
+                ))
+            }
         }
     }
 }
@@ -95,11 +350,22 @@ async fn index() -> &'static str {
 }
 
 #[post("/auth/<pubkey>")]
-async fn request_nonce(pubkey: &str, connection: Connection<'_, Db>) -> WebResponse {
+async fn request_nonce(
+    _limit: RateLimit<AuthRoutes>,
+    pubkey: Result<Base58Pubkey, InvalidParam>,
+    config: &State<Config>,
+    connection: Connection<'_, Db>,
+) -> WebResponse {
     let db = connection.into_inner();
 
+    let pubkey = match pubkey {
+        Ok(pubkey) => pubkey.to_string(),
+        Err(e) => return e.response(),
+    };
+    let pubkey = pubkey.as_str();
+
     let fetch_account = Accounts::find()
-        .filter(entity::accounts::Column::Pubkey.contains(pubkey))
+        .filter(entity::accounts::Column::Pubkey.eq(pubkey))
         .one(db)
         .await;
 
@@ -113,37 +379,54 @@ async fn request_nonce(pubkey: &str, connection: Connection<'_, Db>) -> WebRespo
         }
     };
 
+    // -- Issue a fresh nonce for this login attempt
+    let nonce = match issue_nonce(db, pubkey, config.nonce_ttl_seconds).await {
+        Ok(nonce) => nonce,
+        Err(_) => {
+            let data = json!({ "error": "Failed to issue nonce" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
     let account = if let Some(account) = account_query {
-        let found_account: entity::accounts::ActiveModel = account.into();
+        let mut found_account: entity::accounts::ActiveModel = account.into();
+        found_account.nonce = Set(nonce.nonce.clone());
 
         found_account
     } else {
-        let nonce = Uuid::new_v4().to_string();
-        let account = entity::accounts::ActiveModel {
+        entity::accounts::ActiveModel {
             id: NotSet,
             pubkey: Set(pubkey.to_string()),
-            nonce: Set(nonce.clone()),
+            nonce: Set(nonce.nonce.clone()),
             created_at: Set(Utc::now().naive_utc()),
-        };
-
-        let account_copy = account.clone();
-
-        let save_account = account.save(db).await;
-
-        match save_account {
-            Ok(_) => (),
-            Err(_e) => {
-                let data = json!({ "error": "Failed to save pubkey into database" });
-                let response = SysResponse { data };
+        }
+    };
 
-                return (Status::Accepted, Json(response));
-            }
-        };
+    match account.save(db).await {
+        Ok(_) => (),
+        Err(_e) => {
+            let data = json!({ "error": "Failed to save pubkey into database" });
+            let response = SysResponse { data };
 
-        account_copy
+            return (Status::Accepted, Json(response));
+        }
     };
 
-    let data = json!({ "nonce": account.nonce.as_ref() });
+    let message = SiwsMessage::new(
+        &config.siws,
+        pubkey,
+        &nonce.nonce,
+        nonce.created_at,
+        nonce.expires_at,
+    );
+
+    let data = json!({
+        "nonce": nonce.nonce,
+        "expires_at": nonce.expires_at,
+        "message": message.to_message(),
+    });
     let response = SysResponse { data };
 
     (Status::Accepted, Json(response))
@@ -151,66 +434,232 @@ async fn request_nonce(pubkey: &str, connection: Connection<'_, Db>) -> WebRespo
 
 #[post("/auth", data = "<auth_request>")]
 async fn post_nonce(
-    auth_request: Json<AuthRequest<'_>>,
+    _limit: RateLimit<AuthRoutes>,
+    auth_request: Result<Json<AuthRequest>, JsonError<'_>>,
     config: &State<Config>,
+    jwt_keys: &State<JwtKeys>,
     connection: Connection<'_, Db>,
 ) -> WebResponse {
-    let req = auth_request.into_inner();
+    let req = match auth_request {
+        Ok(auth_request) => auth_request.into_inner(),
+        Err(e) => return body_error(e),
+    };
     let db = connection.into_inner();
+    let pubkey = req.pubkey.to_string();
 
-    // -- Fetch address-appropriate message from database
-    let fetch_accounts = Accounts::find()
-        .filter(entity::accounts::Column::Pubkey.contains(req.pubkey))
-        .one(db)
-        .await;
+    // -- Work out which nonce the wallet signed, either in a message or in a memo
+    let proof = match (&req.message, &req.transaction) {
+        (Some(message), _) => SiwsMessage::parse(message).map(SignInProof::Message),
+        (None, Some(transaction)) => {
+            crypto::verify_transaction(&req.pubkey.0, transaction).map(SignInProof::Transaction)
+        }
+        (None, None) => Err(anyhow!("Sign-in requires a signed message or transaction")),
+    };
 
-    let account = if let Ok(Some(account)) = fetch_accounts {
-        account
-    } else {
-        let data = json!({ "error": "Pubkey has no auth key registered" });
-        let response = SysResponse { data };
+    let proof = match proof {
+        Ok(proof) => proof,
+        Err(e) => {
+            let data = json!({ "error": e.to_string() });
+            let response = SysResponse { data };
 
-        return (Status::Forbidden, Json(response));
+            return (Status::Forbidden, Json(response));
+        }
     };
 
-    // <- Signature
-    match crypto::verify_message(&req, &account.nonce) {
-        Ok(_) => {
-            // -> Api Key
-            if let Ok(token) = create_jwt(req.pubkey, &config.jwt_secret) {
-                let data = json!({ "token": token });
-                let response = SysResponse { data };
+    // -- Fetch the outstanding nonce issued for this login attempt
+    let nonce = match find_nonce(db, &pubkey, proof.nonce()).await {
+        Ok(nonce) => nonce,
+        Err(e) => {
+            let data = json!({ "error": e.to_string() });
+            let response = SysResponse { data };
 
-                (Status::Accepted, Json(response))
-            } else {
-                let data = json!({ "error": "Error creating auth token" });
-                let response = SysResponse { data };
+            return (Status::Forbidden, Json(response));
+        }
+    };
 
-                (Status::InternalServerError, Json(response))
-            }
+    // -- Bind the message to our domain and the issued nonce
+    if let SignInProof::Message(message) = &proof {
+        let expected = SiwsMessage::new(
+            &config.siws,
+            &pubkey,
+            &nonce.nonce,
+            nonce.created_at,
+            nonce.expires_at,
+        );
+
+        if let Err(e) = message.validate(&expected) {
+            let data = json!({ "error": e.to_string() });
+            let response = SysResponse { data };
+
+            return (Status::Forbidden, Json(response));
+        }
+
+        // <- Signature
+        let raw_message = req.message.as_deref().unwrap_or_default();
+
+        if let Err(e) = crypto::verify_message(&req, raw_message) {
+            let data = json!({ "error": e.to_string() });
+            let response = SysResponse { data };
+
+            return (Status::Forbidden, Json(response));
         }
+    }
+
+    // -- Burn the nonce so the same signature cannot be replayed
+    if let Err(e) = consume_nonce(db, &nonce).await {
+        let data = json!({ "error": e.to_string() });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
+
+    // -> Api Key
+    issue_token_pair(db, &pubkey, config, jwt_keys).await
+}
+
+#[post("/auth/refresh", data = "<refresh_request>")]
+async fn refresh_token(
+    _limit: RateLimit<AuthRoutes>,
+    refresh_request: Json<RefreshRequest<'_>>,
+    config: &State<Config>,
+    jwt_keys: &State<JwtKeys>,
+    connection: Connection<'_, Db>,
+) -> WebResponse {
+    let req = refresh_request.into_inner();
+    let db = connection.into_inner();
+
+    let pubkey = match rotate_refresh_token(db, req.refresh_token).await {
+        Ok(pubkey) => pubkey,
         Err(e) => {
             let data = json!({ "error": e.to_string() });
             let response = SysResponse { data };
 
-            (Status::Forbidden, Json(response))
+            return (Status::Unauthorized, Json(response));
         }
+    };
+
+    issue_token_pair(db, &pubkey, config, jwt_keys).await
+}
+
+#[post("/auth/logout")]
+async fn logout(
+    connection: Connection<'_, Db>,
+    auth: ApiKey<'_>,
+) -> WebResponse {
+    let db = connection.into_inner();
+    let claims = auth.claims;
+
+    if let Err(e) = revoke_access_token(db, &claims).await {
+        let data = json!({ "error": e.to_string() });
+        let response = SysResponse { data };
+
+        return (Status::InternalServerError, Json(response));
+    }
+
+    if let Err(e) = revoke_refresh_tokens(db, &claims.sub).await {
+        let data = json!({ "error": e.to_string() });
+        let response = SysResponse { data };
+
+        return (Status::InternalServerError, Json(response));
     }
+
+    let data = json!({ "message": "Logged out" });
+    let response = SysResponse { data };
+
+    (Status::Ok, Json(response))
+}
+
+async fn issue_token_pair(
+    db: &sea_orm::DatabaseConnection,
+    pubkey: &str,
+    config: &Config,
+    jwt_keys: &JwtKeys,
+) -> WebResponse {
+    let role = config.role_for(pubkey);
+
+    let token = match create_jwt(pubkey, role, jwt_keys, config.access_token_ttl_minutes) {
+        Ok(token) => token,
+        Err(_) => {
+            let data = json!({ "error": "Error creating auth token" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    let (refresh_token, issued) =
+        match issue_refresh_token(db, pubkey, config.refresh_token_ttl_days).await {
+            Ok(issued) => issued,
+            Err(_) => {
+                let data = json!({ "error": "Error creating refresh token" });
+                let response = SysResponse { data };
+
+                return (Status::InternalServerError, Json(response));
+            }
+        };
+
+    let data = json!({
+        "token": token,
+        "role": role,
+        "expires_in": config.access_token_ttl_minutes * 60,
+        "refresh_token": refresh_token,
+        "refresh_expires_at": issued.expires_at,
+    });
+    let response = SysResponse { data };
+
+    (Status::Accepted, Json(response))
 }
 
 #[post("/tasks", data = "<task_request>")]
 async fn new_task(
-    task_request: Json<TaskCreate<'_>>,
+    _limit: RateLimit<TaskRoutes>,
+    idempotency_key: IdempotencyKey,
+    task_request: Result<Json<TaskCreate>, JsonError<'_>>,
     connection: Connection<'_, Db>,
-    _auth: ApiKey<'_>,
+    auth: ApiKey<'_>,
+    config: &State<Config>,
+    rpc: &State<SolanaRpc>,
 ) -> WebResponse {
     // <- Receive Task Creation Request
-    let request = task_request.into_inner();
+    let request = match task_request {
+        Ok(task_request) => task_request.into_inner(),
+        Err(e) => return body_error(e),
+    };
     let db = connection.into_inner();
+    let handler = create_task(db, &request, &auth, config, rpc);
+
+    idempotency::once(
+        db,
+        &idempotency_key,
+        "tasks",
+        auth.pubkey(),
+        &request,
+        config.idempotency_ttl_hours,
+        handler,
+    )
+    .await
+}
+
+async fn create_task(
+    db: &sea_orm::DatabaseConnection,
+    request: &TaskCreate,
+    auth: &ApiKey<'_>,
+    config: &Config,
+    rpc: &SolanaRpc,
+) -> WebResponse {
+    let account = request.account.to_string();
+    let mint_address = request.mint_address.to_string();
+
+    if !auth.owns(&account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
 
     // -- Calculate price
-    let price: i32 = match crate::handlers::payment::check_price(request.mint_address).await {
-        Ok(price) => price,
+    let quote = match check_price(rpc, &mint_address).await {
+        Ok(quote) => quote,
         Err(e) => {
             let data = json!({ "error": e.to_string() });
             let response = SysResponse { data };
@@ -221,7 +670,7 @@ async fn new_task(
 
     // -- Query tasks for existing successful rankups
     let fetch_task = History::find()
-        .filter(entity::history::Column::MintAddress.contains(request.mint_address))
+        .filter(entity::history::Column::MintAddress.eq(mint_address.as_str()))
         .filter(entity::history::Column::Success.eq(true))
         .order_by_desc(entity::history::Column::FinishedAt)
         .one(db)
@@ -238,14 +687,22 @@ async fn new_task(
     };
 
     let time_now = Utc::now().naive_utc();
+    let currency = config.currency.currency();
 
     let task = entity::tasks::ActiveModel {
         id: NotSet,
-        account: Set(request.account.to_string()),
-        mint_address: Set(request.mint_address.to_string()),
+        account: Set(account.clone()),
+        mint_address: Set(mint_address.clone()),
         success: Set(false),
         created_at: Set(time_now),
-        price: Set(price),
+        price: Set(quote.price),
+        currency: Set(currency.name()),
+        decimals: Set(currency.decimals() as i32),
+        base_amount: Set(config.currency.base_amount(quote.price)),
+        quote_expires_at: Set(Some(time_now + Duration::minutes(config.quote_ttl_minutes))),
+        status: Set(TaskStatus::AwaitingPayment),
+        rank: Set(Some(quote.rank)),
+        collection: Set(quote.collection),
     };
 
     // -- Check existing successful rankups if past cooldown period
@@ -261,8 +718,8 @@ async fn new_task(
     };
 
     // -- Save Task
-    match task.save(db).await {
-        Ok(_) => (),
+    let return_task = match task.insert(db).await {
+        Ok(task) => task,
         Err(_e) => {
             let data = json!({ "error": "Failed to save task to database" });
             let response = SysResponse { data };
@@ -271,35 +728,19 @@ async fn new_task(
         }
     };
 
-    let fetch_task = Tasks::find()
-        .filter(entity::tasks::Column::MintAddress.contains(request.mint_address))
-        .filter(entity::tasks::Column::Account.contains(request.account))
-        .order_by_desc(entity::tasks::Column::CreatedAt)
-        .one(db)
-        .await;
-
-    let task_query = match fetch_task {
-        Ok(query) => query,
-        Err(e) => {
-            let data = json!({ "error": e.to_string() });
-            let response = SysResponse { data };
-
-            return (Status::InternalServerError, Json(response));
-        }
-    };
-
-    let return_task = match task_query {
-        Some(task) => task,
-        None => {
-            let data = json!({ "error": "Missing task" });
-            let response = SysResponse { data };
+    if let Err(e) = record_transition(db, return_task.id, None, TaskStatus::AwaitingPayment).await {
+        let data = json!({ "error": e.to_string() });
+        let response = SysResponse { data };
 
-            return (Status::NotFound, Json(response));
-        }
-    };
+        return (Status::InternalServerError, Json(response));
+    }
 
     // -> Send Task Created Response
-    let data = json!({ "task_id": return_task.id });
+    let data = json!({
+        "task_id": return_task.id,
+        "price": return_task.price,
+        "quote_expires_at": return_task.quote_expires_at,
+    });
     let response = SysResponse { data };
 
     (Status::Created, Json(response))
@@ -309,11 +750,27 @@ async fn new_task(
 async fn delete_task(
     task_id: i32,
     connection: Connection<'_, Db>,
-    _auth: ApiKey<'_>,
+    _auth: OperatorKey<'_>,
 ) -> WebResponse {
     let db = connection.into_inner();
 
-    let result = Tasks::delete_by_id(task_id).exec(db).await;
+    let task = match Tasks::find_by_id(task_id).one(db).await {
+        Ok(Some(task)) => task,
+        Ok(None) => {
+            let data = json!({ "error": "Task does not exist" });
+            let response = SysResponse { data };
+
+            return (Status::NotFound, Json(response));
+        }
+        Err(_) => {
+            let data = json!({ "error": "Database query failed" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    let result = Tasks::delete_by_id(task.id).exec(db).await;
 
     match result {
         Ok(_) => {
@@ -333,14 +790,19 @@ async fn delete_task(
 
 #[post("/tasks/delete/account/<account>")]
 async fn delete_tasks_account(
-    account: &str,
+    account: Result<Base58Pubkey, InvalidParam>,
     connection: Connection<'_, Db>,
-    _auth: ApiKey<'_>,
+    _auth: AdminKey<'_>,
 ) -> WebResponse {
     let db = connection.into_inner();
 
+    let account = match account {
+        Ok(account) => account.to_string(),
+        Err(e) => return e.response(),
+    };
+
     let result = Tasks::delete_many()
-        .filter(entity::tasks::Column::Account.contains(account))
+        .filter(entity::tasks::Column::Account.eq(account.as_str()))
         .exec(db)
         .await;
 
@@ -362,12 +824,46 @@ async fn delete_tasks_account(
 
 #[post("/payments", data = "<payment_request>")]
 async fn new_payment(
-    payment_request: Json<PaymentCreate<'_>>,
+    _limit: RateLimit<PaymentRoutes>,
+    idempotency_key: IdempotencyKey,
+    payment_request: Result<Json<PaymentCreate>, JsonError<'_>>,
     connection: Connection<'_, Db>,
-    _auth: ApiKey<'_>,
+    auth: ApiKey<'_>,
+    config: &State<Config>,
 ) -> WebResponse {
-    let request = payment_request.into_inner();
+    let request = match payment_request {
+        Ok(payment_request) => payment_request.into_inner(),
+        Err(e) => return body_error(e),
+    };
     let db = connection.into_inner();
+    let handler = create_payment(db, &request, &auth, config);
+
+    idempotency::once(
+        db,
+        &idempotency_key,
+        "payments",
+        auth.pubkey(),
+        &request,
+        config.idempotency_ttl_hours,
+        handler,
+    )
+    .await
+}
+
+async fn create_payment(
+    db: &sea_orm::DatabaseConnection,
+    request: &PaymentCreate,
+    auth: &ApiKey<'_>,
+    config: &Config,
+) -> WebResponse {
+    let account = request.account.to_string();
+
+    if !auth.owns(&account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
 
     let query = Tasks::find_by_id(request.task_id).one(db).await;
 
@@ -393,66 +889,111 @@ async fn new_payment(
         }
     };
 
-    let new_payment = entity::payments::ActiveModel {
-        id: NotSet,
-        account: Set(request.account.to_string()),
-        success: Set(false),
-        created_at: Set(Utc::now().naive_utc()),
-        tx: Set(String::from("none")),
-        task_id: Set(request.task_id),
-        amount: Set(task.price),
-    };
+    if !auth.owns(&task.account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
 
-    match new_payment.save(db).await {
-        Ok(_) => (),
-        Err(e) => {
-            let data = json!({ "error": e.to_string() });
-            let response = SysResponse { data };
+        return (Status::Forbidden, Json(response));
+    }
 
-            return (Status::InternalServerError, Json(response));
-        }
+    if !quote_valid(task.quote_expires_at, Utc::now().naive_utc()) {
+        // -- The task may already have been marked expired
+        let _ = transition(
+            db,
+            task.id,
+            TaskStatus::AwaitingPayment,
+            TaskStatus::Expired,
+        )
+        .await;
+
+        let data = json!({ "error": "Price quote has expired, requote the task" });
+        let response = SysResponse { data };
+
+        return (Status::Gone, Json(response));
     }
 
-    let return_payment = Payments::find()
-        .filter(entity::payments::Column::TaskId.eq(request.task_id))
-        .order_by_desc(entity::payments::Column::CreatedAt)
-        .one(db)
-        .await;
+    if task.status != TaskStatus::AwaitingPayment {
+        let data = json!({ "error": "Task is no longer awaiting payment", "status": task.status });
+        let response = SysResponse { data };
 
-    let payment_query = match return_payment {
-        Ok(query) => query,
+        return (Status::Conflict, Json(response));
+    }
+
+    let currency = match Currency::parse(&task.currency, task.decimals) {
+        Ok(currency) => currency,
         Err(e) => {
             let data = json!({ "error": e.to_string() });
             let response = SysResponse { data };
 
-            return (Status::NotFound, Json(response));
+            return (Status::InternalServerError, Json(response));
         }
     };
 
-    let payment_clone = match payment_query {
-        Some(payment) => payment,
-        None => {
-            let data = json!({ "error": "Missing payment object" });
+    // -- Fresh throwaway key the wallet adds to the transfer so it can be found on chain
+    let reference = Keypair::new().pubkey();
+
+    let new_payment = entity::payments::ActiveModel {
+        id: NotSet,
+        account: Set(account),
+        success: Set(false),
+        created_at: Set(Utc::now().naive_utc()),
+        tx: Set(String::from("none")),
+        task_id: Set(request.task_id),
+        amount: Set(task.price),
+        currency: Set(task.currency.clone()),
+        decimals: Set(task.decimals),
+        base_amount: Set(task.base_amount),
+        confirmed_at: Set(None),
+        failure_reason: Set(None),
+        reference: Set(Some(reference.to_string())),
+        status: Set(PaymentStatus::Pending),
+        quote_expires_at: Set(task.quote_expires_at),
+    };
+
+    let payment_clone = match new_payment.insert(db).await {
+        Ok(payment) => payment,
+        Err(e) => {
+            let data = json!({ "error": e.to_string() });
             let response = SysResponse { data };
 
-            return (Status::NotFound, Json(response));
+            return (Status::InternalServerError, Json(response));
         }
     };
 
+    if let Err(e) = record_transition(db, payment_clone.id, None, PaymentStatus::Pending).await {
+        let data = json!({ "error": e.to_string() });
+        let response = SysResponse { data };
+
+        return (Status::InternalServerError, Json(response));
+    }
+
+    let message = format!("Rank-up for {}", task.mint_address);
+    let memo = format!("{}{}", PAYMENT_MEMO_PREFIX, payment_clone.id);
+    let transfer_request = TransferRequest {
+        recipient: &config.treasury.0,
+        currency: &currency,
+        base_amount: payment_clone.base_amount,
+        reference: &reference,
+        label: &config.solana_pay_label,
+        message: &message,
+        memo: &memo,
+    };
+
     // -> paymentid
-    let data = json!({ "payment_id": payment_clone.id });
+    let data = json!({
+        "payment_id": payment_clone.id,
+        "reference": reference.to_string(),
+        "url": transfer_request.to_url(),
+    });
     let response = SysResponse { data };
 
     (Status::Created, Json(response))
 }
 
 #[get("/tasks/id/<task_id>")]
-async fn get_task(task_id: &str, connection: Connection<'_, Db>, _auth: ApiKey<'_>) -> WebResponse {
+async fn get_task(task_id: i32, connection: Connection<'_, Db>, auth: ApiKey<'_>) -> WebResponse {
     let db = connection.into_inner();
-    let fetch = Tasks::find()
-        .filter(entity::tasks::Column::Id.contains(task_id))
-        .one(db)
-        .await;
+    let fetch = Tasks::find_by_id(task_id).one(db).await;
 
     let query = match fetch {
         Ok(task) => task,
@@ -474,25 +1015,175 @@ async fn get_task(task_id: &str, connection: Connection<'_, Db>, _auth: ApiKey<'
         }
     };
 
+    if !auth.owns(&task.account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
+
+    let transitions = match transitions_for(db, TaskStatus::KIND, task.id).await {
+        Ok(transitions) => transitions,
+        Err(_) => {
+            let data = json!({ "error": "Failed to query task status" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    let data = json!({ "task": task, "transitions": transitions });
+    let response = SysResponse { data };
+
+    (Status::Accepted, Json(response))
+}
+
+#[post("/tasks/id/<task_id>/requote")]
+async fn requote_task(
+    _limit: RateLimit<TaskRoutes>,
+    task_id: i32,
+    connection: Connection<'_, Db>,
+    auth: ApiKey<'_>,
+    config: &State<Config>,
+    rpc: &State<SolanaRpc>,
+) -> WebResponse {
+    let db = connection.into_inner();
+
+    let task = match Tasks::find_by_id(task_id).one(db).await {
+        Ok(Some(task)) => task,
+        Ok(None) => {
+            let data = json!({ "error": "Task does not exist" });
+            let response = SysResponse { data };
+
+            return (Status::NotFound, Json(response));
+        }
+        Err(_) => {
+            let data = json!({ "error": "Failed to query tasks" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    if !auth.owns(&task.account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
+
+    // -- Only unpaid tasks can be requoted; an expired one is reopened
+    let reopened = match task.status {
+        TaskStatus::AwaitingPayment => Ok(()),
+        TaskStatus::Expired => {
+            transition(
+                db,
+                task.id,
+                TaskStatus::Expired,
+                TaskStatus::AwaitingPayment,
+            )
+            .await
+        }
+        status => Err(TransitionError::Illegal(
+            status.to_value(),
+            TaskStatus::AwaitingPayment.to_value(),
+        )),
+    };
+
+    match reopened {
+        Ok(_) => (),
+        Err(TransitionError::Database(e)) => {
+            let data = json!({ "error": e.to_string() });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+        Err(e) => {
+            let data =
+                json!({ "error": "Task is no longer awaiting payment", "reason": e.to_string() });
+            let response = SysResponse { data };
+
+            return (Status::Conflict, Json(response));
+        }
+    }
+
+    let quote = match check_price(rpc, &task.mint_address).await {
+        Ok(quote) => quote,
+        Err(e) => {
+            let data = json!({ "error": e.to_string() });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    let currency = config.currency.currency();
+    let base_amount = config.currency.base_amount(quote.price);
+    let quote_expires_at = Utc::now().naive_utc() + Duration::minutes(config.quote_ttl_minutes);
+
+    let mut requoted: entity::tasks::ActiveModel = task.into();
+    requoted.status = Set(TaskStatus::AwaitingPayment);
+    requoted.price = Set(quote.price);
+    requoted.currency = Set(currency.name());
+    requoted.decimals = Set(currency.decimals() as i32);
+    requoted.base_amount = Set(base_amount);
+    requoted.quote_expires_at = Set(Some(quote_expires_at));
+    requoted.rank = Set(Some(quote.rank));
+    requoted.collection = Set(quote.collection);
+
+    let task = match requoted.update(db).await {
+        Ok(task) => task,
+        Err(_) => {
+            let data = json!({ "error": "Failed to save task to database" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    // -- Pending payments keep the amount and expiry they were quoted, since
+    // -- the wallet may already have signed a transfer for them
     let data = json!({ "task": task });
     let response = SysResponse { data };
 
     (Status::Accepted, Json(response))
 }
 
-#[get("/tasks/account/<account>")]
+#[get("/tasks/account/<account>?<status>")]
 async fn list_tasks(
-    account: &str,
+    account: Result<Base58Pubkey, InvalidParam>,
+    status: Option<String>,
     connection: Connection<'_, Db>,
-    _auth: ApiKey<'_>,
+    auth: ApiKey<'_>,
 ) -> WebResponse {
     let db = connection.into_inner();
-    let fetch = Tasks::find()
-        .filter(entity::tasks::Column::Account.contains(account))
-        .order_by_desc(entity::tasks::Column::Id)
-        .paginate(db, 10)
-        .fetch()
-        .await;
+
+    let account = match account {
+        Ok(account) => account.to_string(),
+        Err(e) => return e.response(),
+    };
+
+    if !auth.owns(&account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
+
+    let status = match status_filter::<TaskStatus>(status) {
+        Ok(status) => status,
+        Err(response) => return response,
+    };
+
+    let mut query = Tasks::find()
+        .filter(entity::tasks::Column::Account.eq(account.as_str()))
+        .order_by_desc(entity::tasks::Column::Id);
+
+    if let Some(status) = status {
+        query = query.filter(entity::tasks::Column::Status.eq(status));
+    }
+
+    let fetch = query.paginate(db, 10).fetch().await;
 
     let tasks = match fetch {
         Ok(tasks) => tasks,
@@ -510,17 +1201,47 @@ async fn list_tasks(
     (Status::Accepted, Json(response))
 }
 
+#[get("/jobs/id/<job_id>")]
+async fn get_job(job_id: i32, connection: Connection<'_, Db>, auth: ApiKey<'_>) -> WebResponse {
+    let db = connection.into_inner();
+
+    let job = match Jobs::find_by_id(job_id).one(db).await {
+        Ok(Some(job)) => job,
+        Ok(None) => {
+            let data = json!({ "error": "Job does not exist" });
+            let response = SysResponse { data };
+
+            return (Status::NotFound, Json(response));
+        }
+        Err(_) => {
+            let data = json!({ "error": "Failed to query job" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    if !auth.owns(&job.account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
+
+    let data = json!({ "job": job });
+    let response = SysResponse { data };
+
+    (Status::Ok, Json(response))
+}
+
 #[get("/payments/id/<payment_id>")]
 async fn get_payment(
-    payment_id: &str,
+    payment_id: i32,
     connection: Connection<'_, Db>,
-    _auth: ApiKey<'_>,
+    auth: ApiKey<'_>,
 ) -> WebResponse {
     let db = connection.into_inner();
-    let fetch = Payments::find()
-        .filter(entity::payments::Column::Id.contains(payment_id))
-        .one(db)
-        .await;
+    let fetch = Payments::find_by_id(payment_id).one(db).await;
 
     let query = match fetch {
         Ok(query) => query,
@@ -542,27 +1263,126 @@ async fn get_payment(
         }
     };
 
-    let data = json!({ "payment": payment });
+    if !auth.owns(&payment.account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
+
+    let transitions = match transitions_for(db, PaymentStatus::KIND, payment.id).await {
+        Ok(transitions) => transitions,
+        Err(_) => {
+            let data = json!({ "error": "Failed to query payment status" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    let data = json!({ "payment": payment, "transitions": transitions });
     let response = SysResponse { data };
 
     (Status::BadRequest, Json(response))
 }
 
-#[post("/payments/account/<account>")]
-async fn list_payments(
-    account: &str,
+#[get("/payments/reference/<reference>")]
+async fn get_payment_by_reference(
+    reference: Result<Base58Pubkey, InvalidParam>,
     connection: Connection<'_, Db>,
-    _auth: ApiKey<'_>,
+    auth: ApiKey<'_>,
+    config: &State<Config>,
+    rpc: &State<SolanaRpc>,
 ) -> WebResponse {
+    let reference = match reference {
+        Ok(reference) => reference,
+        Err(e) => return e.response(),
+    };
     let db = connection.into_inner();
 
-    let fetch_payments = Payments::find()
-        .filter(entity::payments::Column::Account.contains(account))
-        .order_by_desc(entity::payments::Column::Id)
-        .paginate(db, 10)
-        .fetch()
+    let fetch = Payments::find()
+        .filter(entity::payments::Column::Reference.eq(reference.to_string()))
+        .one(db)
         .await;
 
+    let payment = match fetch {
+        Ok(Some(payment)) => payment,
+        Ok(None) => {
+            let data = json!({ "error": "Payment does not exist" });
+            let response = SysResponse { data };
+
+            return (Status::NotFound, Json(response));
+        }
+        Err(_) => {
+            let data = json!({ "error": "Failed to query payment" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    if !auth.owns(&payment.account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
+
+    let signature = match find_reference(rpc, &reference.0, &config.payment_commitment).await {
+        Ok(signature) => signature,
+        Err(e) => {
+            let data = json!({ "error": e.to_string() });
+            let response = SysResponse { data };
+
+            return (Status::BadGateway, Json(response));
+        }
+    };
+
+    let data = json!({
+        "payment": payment,
+        "signature": signature.map(|signature| signature.to_string()),
+    });
+    let response = SysResponse { data };
+
+    (Status::Accepted, Json(response))
+}
+
+#[post("/payments/account/<account>?<status>")]
+async fn list_payments(
+    account: Result<Base58Pubkey, InvalidParam>,
+    status: Option<String>,
+    connection: Connection<'_, Db>,
+    auth: ApiKey<'_>,
+) -> WebResponse {
+    let db = connection.into_inner();
+
+    let account = match account {
+        Ok(account) => account.to_string(),
+        Err(e) => return e.response(),
+    };
+
+    if !auth.owns(&account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
+
+    let status = match status_filter::<PaymentStatus>(status) {
+        Ok(status) => status,
+        Err(response) => return response,
+    };
+
+    let mut query = Payments::find()
+        .filter(entity::payments::Column::Account.eq(account.as_str()))
+        .order_by_desc(entity::payments::Column::Id);
+
+    if let Some(status) = status {
+        query = query.filter(entity::payments::Column::Status.eq(status));
+    }
+
+    let fetch_payments = query.paginate(db, 10).fetch().await;
+
     let payments = match fetch_payments {
         Ok(payments) => payments,
         Err(_) => {
@@ -573,26 +1393,79 @@ async fn list_payments(
         }
     };
 
+    let payment_ids: Vec<i32> = payments.iter().map(|payment| payment.id).collect();
+    let fetch_refunds = Refunds::find()
+        .filter(entity::refunds::Column::PaymentId.is_in(payment_ids))
+        .all(db)
+        .await;
+
+    let refunds = match fetch_refunds {
+        Ok(refunds) => refunds,
+        Err(_) => {
+            let data = json!({ "error": "Failed to fetch refunds" });
+            let response = SysResponse { data };
+
+            return (Status::InternalServerError, Json(response));
+        }
+    };
+
+    let payments: Vec<serde_json::Value> = payments
+        .into_iter()
+        .map(|payment| {
+            let refund = refunds
+                .iter()
+                .find(|refund| refund.payment_id == payment.id)
+                .map(|refund| json!({ "status": refund.status, "signature": refund.signature }));
+
+            // -- Keep the payment fields at the top level for existing clients
+            let mut payment = json!(payment);
+            payment["refund"] = json!(refund);
+
+            payment
+        })
+        .collect();
+
     let data = json!({ "payments": payments });
     let response = SysResponse { data };
 
     (Status::Accepted, Json(response))
 }
 
-#[post("/history/account/<account>")]
+#[post("/history/account/<account>?<status>")]
 async fn list_history(
-    account: &str,
+    account: Result<Base58Pubkey, InvalidParam>,
+    status: Option<String>,
     connection: Connection<'_, Db>,
-    _auth: ApiKey<'_>,
+    auth: ApiKey<'_>,
 ) -> WebResponse {
     let db = connection.into_inner();
 
-    let fetch_history = History::find()
-        .filter(entity::history::Column::Account.contains(account))
-        .order_by_desc(entity::history::Column::Id)
-        .paginate(db, 10)
-        .fetch()
-        .await;
+    let account = match account {
+        Ok(account) => account.to_string(),
+        Err(e) => return e.response(),
+    };
+
+    if !auth.owns(&account) {
+        let data = json!({ "error": "Account does not belong to the authenticated wallet" });
+        let response = SysResponse { data };
+
+        return (Status::Forbidden, Json(response));
+    }
+
+    let status = match status_filter::<HistoryStatus>(status) {
+        Ok(status) => status,
+        Err(response) => return response,
+    };
+
+    let mut query = History::find()
+        .filter(entity::history::Column::Account.eq(account.as_str()))
+        .order_by_desc(entity::history::Column::Id);
+
+    if let Some(status) = status {
+        query = query.filter(entity::history::Column::Status.eq(status));
+    }
+
+    let fetch_history = query.paginate(db, 10).fetch().await;
 
     let history = match fetch_history {
         Ok(history) => history,
@@ -612,138 +1485,238 @@ async fn list_history(
 
 #[post("/payments/hook", data = "<payment_receive>")]
 async fn receive_payment(
-    payment_receive: Json<PaymentReceive<'_>>,
+    payment_receive: SignedPayload<PaymentReceive>,
     connection: Connection<'_, Db>,
-    _auth: ApiKey<'_>,
+    config: &State<Config>,
+    rpc: &State<SolanaRpc>,
 ) -> WebResponse {
-    type PaymentsModel = entity::payments::Model;
-    type TasksModel = entity::tasks::Model;
+    let request = payment_receive.payload;
+    let db = connection.into_inner();
 
-    let request = payment_receive.into_inner();
+    let processed =
+        match process_payment(db, config, rpc, request.payment_id, &request.tx_id.0).await {
+            Ok(processed) => processed,
+            Err(e) => return e.response(),
+        };
+
+    let data = json!({
+        "message": "Payment confirmed, rank-up queued",
+        "slot": processed.slot,
+        "job_id": processed.job_id,
+    });
+    let response = SysResponse { data };
+
+    (Status::Accepted, Json(response))
+}
+
+#[get("/.well-known/jwks.json")]
+async fn jwks(jwt_keys: &State<JwtKeys>) -> Json<serde_json::Value> {
+    Json(json!({ "keys": jwt_keys.jwks }))
+}
+
+#[post("/integrations", data = "<integration_request>")]
+async fn new_integration(
+    integration_request: Json<IntegrationCreate<'_>>,
+    connection: Connection<'_, Db>,
+    _auth: AdminKey<'_>,
+) -> WebResponse {
+    let request = integration_request.into_inner();
     let db = connection.into_inner();
 
-    let signature = request.tx_id;
-    let b58 = bs58::decode(signature).into_vec();
+    let secret: [u8; 32] = rand::random();
+    let secret = hex::encode(secret);
+
+    let integration = entity::integrations::ActiveModel {
+        id: NotSet,
+        name: Set(request.name.to_string()),
+        secret: Set(secret.clone()),
+        active: Set(true),
+        created_at: Set(Utc::now().naive_utc()),
+    };
 
-    let signature = match b58 {
-        Ok(bytes) => bytes,
+    let integration = match integration.insert(db).await {
+        Ok(integration) => integration,
         Err(_) => {
-            let data = json!({ "error": "Failed to decode signature" });
+            let data = json!({ "error": "Failed to save integration" });
             let response = SysResponse { data };
 
             return (Status::InternalServerError, Json(response));
         }
     };
 
-    let _signature = Signature::new(&signature);
+    // -> The secret is only ever returned once
+    let data = json!({ "integration": integration, "secret": secret });
+    let response = SysResponse { data };
 
-    let fetch_payment_by_id: Result<Option<PaymentsModel>, sea_orm::DbErr> = Payments::find()
-        .filter(entity::payments::Column::Id.eq(request.payment_id))
-        .one(db)
-        .await;
+    (Status::Created, Json(response))
+}
 
-    let payment: PaymentsModel = {
-        let fetch_payment: Option<PaymentsModel> = match fetch_payment_by_id {
-            Ok(found) => found,
-            Err(_) => {
-                let data = json!({ "error": "Failed to fetch payment" });
-                let response = SysResponse { data };
+#[post("/integrations/disable/id/<integration_id>")]
+async fn disable_integration(
+    integration_id: i32,
+    connection: Connection<'_, Db>,
+    _auth: AdminKey<'_>,
+) -> WebResponse {
+    let db = connection.into_inner();
 
-                return (Status::InternalServerError, Json(response));
-            }
-        };
+    let result = Integrations::update_many()
+        .col_expr(entity::integrations::Column::Active, Expr::value(false))
+        .filter(entity::integrations::Column::Id.eq(integration_id))
+        .exec(db)
+        .await;
 
-        match fetch_payment {
-            Some(payment) => payment,
-            None => {
-                let data = json!({ "error": "Payment does not exist" });
-                let response = SysResponse { data };
+    match result {
+        Ok(updated) if updated.rows_affected == 1 => {
+            let data = json!({ "message": "Integration has been disabled" });
+            let response = SysResponse { data };
 
-                return (Status::NotFound, Json(response));
-            }
+            (Status::Ok, Json(response))
         }
-    };
+        Ok(_) => {
+            let data = json!({ "error": "Integration does not exist" });
+            let response = SysResponse { data };
 
-    let fetch_task_by_id = Tasks::find_by_id(payment.task_id).one(db).await;
+            (Status::NotFound, Json(response))
+        }
+        Err(_) => {
+            let data = json!({ "error": "Failed to disable integration" });
+            let response = SysResponse { data };
 
-    let task: TasksModel = {
-        let fetch_task = match fetch_task_by_id {
-            Ok(fetch) => fetch,
-            Err(_) => {
-                let data = json!({ "error": "Database query failed" });
-                let response = SysResponse { data };
+            (Status::InternalServerError, Json(response))
+        }
+    }
+}
 
-                return (Status::InternalServerError, Json(response));
-            }
-        };
+#[get("/refunds?<status>")]
+async fn list_refunds(
+    status: Option<String>,
+    connection: Connection<'_, Db>,
+    _auth: OperatorKey<'_>,
+) -> WebResponse {
+    let db = connection.into_inner();
 
-        match fetch_task {
-            Some(task) => task,
-            None => {
-                let data = json!({ "error": "Task does not exist" });
-                let response = SysResponse { data };
+    if let Some(status) = &status {
+        if !REFUND_STATUSES.contains(&status.as_str()) {
+            let data = json!({
+                "error": "Unknown status",
+                "value": status,
+                "accepted": REFUND_STATUSES,
+            });
+            let response = SysResponse { data };
 
-                return (Status::NotFound, Json(response));
-            }
+            return (Status::UnprocessableEntity, Json(response));
         }
-    };
+    }
 
-    let mint_address = task.mint_address.to_owned();
+    let mut query = Refunds::find().order_by_desc(entity::refunds::Column::Id);
 
-    let new_history = entity::history::ActiveModel {
-        id: NotSet,
-        account: Set(payment.account),
-        mint_address: Set(mint_address.clone()),
-        finished_at: Set(Utc::now().naive_utc()),
-        payment_id: Set(payment.id),
-        task_id: Set(task.id),
-        signature: Set(request.tx_id.to_string()),
-        price: Set(task.price),
-        success: Set(false),
-    };
+    if let Some(status) = status {
+        query = query.filter(entity::refunds::Column::Status.eq(status));
+    }
 
-    let mut new_history = match new_history.save(db).await {
-        Ok(history) => history,
-        Err(e) => {
-            let data = json!({ "error": e.to_string() });
+    let refunds = match query.all(db).await {
+        Ok(refunds) => refunds,
+        Err(_) => {
+            let data = json!({ "error": "Failed to fetch refunds" });
             let response = SysResponse { data };
 
             return (Status::InternalServerError, Json(response));
         }
     };
 
-    let success = match handle_update(&mint_address).await {
-        Ok(val) => val,
-        Err(e) => {
-            let data = json!({ "error": e.to_string() });
+    let data = json!({ "refunds": refunds });
+    let response = SysResponse { data };
+
+    (Status::Accepted, Json(response))
+}
+
+#[post("/refunds/approve/id/<refund_id>")]
+async fn approve_refund(
+    refund_id: i32,
+    connection: Connection<'_, Db>,
+    auth: OperatorKey<'_>,
+    config: &State<Config>,
+    rpc: &State<SolanaRpc>,
+) -> WebResponse {
+    let db = connection.into_inner();
+
+    let refund = match send_refund(db, config, rpc, refund_id, auth.0.pubkey()).await {
+        Ok(refund) => refund,
+        Err(e) => return e.response(),
+    };
+
+    let data = json!({ "refund": refund });
+    let response = SysResponse { data };
+
+    (Status::Ok, Json(response))
+}
+
+#[get("/reports/revenue?<from>&<to>&<format>")]
+async fn revenue_export(
+    from: Option<String>,
+    to: Option<String>,
+    format: Option<String>,
+    connection: Connection<'_, Db>,
+    _auth: OperatorKey<'_>,
+) -> ReportResponse {
+    let db = connection.into_inner();
+
+    let from = match parse_day(from) {
+        Ok(from) => from,
+        Err(response) => return ReportResponse::Json(response),
+    };
+
+    let to = match parse_day(to) {
+        Ok(to) => to,
+        Err(response) => return ReportResponse::Json(response),
+    };
+
+    let csv = match format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(_) => {
+            let data = json!({ "error": "Format must be json or csv" });
             let response = SysResponse { data };
 
-            return (Status::InternalServerError, Json(response));
+            return ReportResponse::Json((Status::UnprocessableEntity, Json(response)));
         }
     };
 
-    new_history.success = Set(success);
-
-    match new_history.save(db).await {
-        Ok(_) => (),
-        Err(e) => {
-            let data = json!({ "error": e.to_string() });
+    let report = match revenue_report(db, from, to).await {
+        Ok(report) => report,
+        Err(_) => {
+            let data = json!({ "error": "Failed to build revenue report" });
             let response = SysResponse { data };
 
-            return (Status::InternalServerError, Json(response));
+            return ReportResponse::Json((Status::InternalServerError, Json(response)));
         }
+    };
+
+    if csv {
+        let disposition = Header::new(
+            "Content-Disposition",
+            "attachment; filename=\"revenue.csv\"",
+        );
+
+        return ReportResponse::Csv(report.to_csv(), ContentType::CSV, disposition);
     }
 
-    let data = json!({ "message": "Payment successful" });
+    let data = json!({ "report": report });
     let response = SysResponse { data };
 
-    (Status::Accepted, Json(response))
+    ReportResponse::Json((Status::Ok, Json(response)))
 }
 
 async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
     let conn = &Db::fetch(&rocket).unwrap().conn;
-    let _ = migration::Migrator::up(conn, None).await;
-    Ok(rocket)
+
+    match migration::Migrator::up(conn, None).await {
+        Ok(_) => Ok(rocket),
+        Err(e) => {
+            log::error!("Failed to run migrations: {}", e);
+            Err(rocket)
+        }
+    }
 }
 
 #[derive(Responder)]
@@ -755,6 +1728,27 @@ struct OptionsResponder<'a> {
     credentials: Header<'a>,
 }
 
+#[catch(422)]
+async fn unprocessable_entity(req: &Request<'_>) -> WebResponse {
+    if let Some(field) = req.local_cache(|| Option::<InvalidField>::None) {
+        return field.response();
+    }
+
+    let data =
+        json!({ "error": "Request body is malformed or has an invalid pubkey or signature" });
+    let response = SysResponse { data };
+
+    (Status::UnprocessableEntity, Json(response))
+}
+
+#[catch(429)]
+async fn too_many_requests() -> WebResponse {
+    let data = json!({ "error": "Too many requests" });
+    let response = SysResponse { data };
+
+    (Status::TooManyRequests, Json(response))
+}
+
 #[catch(default)]
 async fn options_for_all() -> OptionsResponder<'static> {
     OptionsResponder {
@@ -771,30 +1765,101 @@ async fn rocket() -> _ {
     let server = rocket::build();
     let figment = server.figment();
 
-    let _config: Config = figment.extract().expect("Config file not present");
+    let config: Config = figment.extract().expect("Config file not present");
+    let jwt_keys =
+        JwtKeys::load(&config.jwt_keys, &config.jwt_signing_kid).expect("Failed to load JWT keys");
+    // -- Nothing is limited without a `[rate_limit]` section, but a malformed
+    // -- one stops the server
+    let rate_limit: RateLimitConfig = if figment.contains("rate_limit") {
+        figment.extract_inner("rate_limit").expect("Invalid rate limit config")
+    } else {
+        RateLimitConfig::default()
+    };
+    let rate_limiter = RateLimiter::new(rate_limit).expect("Invalid rate limit config");
+    let rpc = SolanaRpc::new(&config.rpc).expect("Failed to set up RPC clients");
+    secrets::check(&config, &rpc)
+        .await
+        .expect("Configured keys failed the startup check");
 
     server
         .attach(CORS)
         .attach(Db::init())
         .attach(AdHoc::try_on_ignite("Migrations", run_migrations))
         .attach(AdHoc::config::<Config>())
-        .register("/api", catchers![options_for_all])
+        .manage(jwt_keys)
+        .manage(rate_limiter)
+        .manage(rpc)
+        .attach(AdHoc::on_liftoff("RPC health checks", |rocket| {
+            Box::pin(async move {
+                let config: Config = rocket.figment().extract().expect("Config file not present");
+                let rpc = rocket.state::<SolanaRpc>().unwrap().clone();
+
+                tokio::spawn(rpc::run_health_checks(rpc, config.rpc.health_check_seconds));
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Job workers", |rocket| {
+            Box::pin(async move {
+                let config: Config = rocket.figment().extract().expect("Config file not present");
+                let config = Arc::new(config);
+                let db = Db::fetch(rocket).unwrap().conn.clone();
+                let rpc = rocket.state::<SolanaRpc>().unwrap().clone();
+
+                // -- Jobs left running by a previous process are picked up again
+                // -- once their visibility timeout runs out
+                for _ in 0..config.jobs.workers {
+                    tokio::spawn(jobs::run_worker(db.clone(), config.clone(), rpc.clone()));
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Treasury watcher", |rocket| {
+            Box::pin(async move {
+                let config: Config = rocket.figment().extract().expect("Config file not present");
+
+                if config.watcher.enabled {
+                    let db = Db::fetch(rocket).unwrap().conn.clone();
+                    let rpc = rocket.state::<SolanaRpc>().unwrap().clone();
+                    tokio::spawn(watcher::run(db, config, rpc));
+                }
+            })
+        }))
+        .attach(AdHoc::on_response("Retry-After", |req, res| {
+            Box::pin(async move {
+                if let RetryAfter(Some(seconds)) = req.local_cache(|| RetryAfter(None)) {
+                    res.set_header(Header::new("Retry-After", seconds.to_string()));
+                }
+            })
+        }))
+        .register(
+            "/api",
+            catchers![unprocessable_entity, too_many_requests, options_for_all],
+        )
         .mount(
             "/api",
             routes![
                 index,
                 request_nonce,
                 post_nonce,
+                refresh_token,
+                logout,
                 new_task,
                 new_payment,
                 get_task,
+                requote_task,
                 get_payment,
+                get_payment_by_reference,
+                get_job,
                 list_tasks,
                 list_payments,
                 list_history,
                 delete_task,
                 delete_tasks_account,
-                receive_payment
+                receive_payment,
+                new_integration,
+                disable_integration,
+                list_refunds,
+                approve_refund,
+                revenue_export
             ],
         )
+        .mount("/", routes![jwks])
 }
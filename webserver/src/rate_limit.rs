@@ -0,0 +1,218 @@
+use crate::pool::Db;
+use crate::util::{decode_jwt, jwt::JwtKeys};
+use anyhow::anyhow;
+use chrono::Utc;
+use entity::rate_limits::{self, Entity as RateLimits};
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+    serde::Deserialize,
+};
+use sea_orm::{entity::*, query::*, ConnectionTrait, DatabaseConnection, DbErr, Statement};
+use sea_orm_rocket::Database;
+use std::{collections::HashMap, marker::PhantomData, sync::Mutex};
+
+/// Counters are dropped once the in-memory store grows past this many keys.
+const MAX_MEMORY_KEYS: usize = 10_000;
+
+/// Counts a hit in one statement, creating the key's counter or starting it
+/// over in a new window, so concurrent hits are never lost.
+const COUNT_HIT: &str = "INSERT INTO \"rate_limits\" (\"key\", \"window_start\", \"count\") VALUES (?, ?, 1) \
+    ON CONFLICT (\"key\") DO UPDATE SET \
+    \"count\" = CASE WHEN \"rate_limits\".\"window_start\" = excluded.\"window_start\" THEN \"rate_limits\".\"count\" + 1 ELSE 1 END, \
+    \"window_start\" = excluded.\"window_start\"";
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    #[default]
+    Memory,
+    Database,
+}
+
+#[derive(Deserialize)]
+pub struct GroupLimits {
+    pub window_seconds: i64,
+    pub per_ip: Option<i32>,
+    pub per_wallet: Option<i32>,
+}
+
+/// `[default.rate_limit]` section of `Rocket.toml`. Route groups without an
+/// entry are not limited.
+#[derive(Deserialize, Default)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub store: StoreKind,
+    #[serde(default)]
+    pub groups: HashMap<String, GroupLimits>,
+}
+
+pub trait RouteGroup: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+pub struct AuthRoutes;
+
+impl RouteGroup for AuthRoutes {
+    const NAME: &'static str = "auth";
+}
+
+pub struct TaskRoutes;
+
+impl RouteGroup for TaskRoutes {
+    const NAME: &'static str = "tasks";
+}
+
+pub struct PaymentRoutes;
+
+impl RouteGroup for PaymentRoutes {
+    const NAME: &'static str = "payments";
+}
+
+/// Request guard counting the request against the buckets of route group `G`.
+pub struct RateLimit<G: RouteGroup>(PhantomData<G>);
+
+/// Seconds until the exceeded bucket resets, cached on the request so the
+/// response can carry a `Retry-After` header.
+pub struct RetryAfter(pub Option<i64>);
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    counters: Mutex<HashMap<String, (i64, i32)>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> anyhow::Result<Self> {
+        for (name, limits) in &config.groups {
+            if limits.window_seconds <= 0 {
+                return Err(anyhow!(
+                    "Rate limit group {} needs a positive window_seconds",
+                    name
+                ));
+            }
+        }
+
+        Ok(RateLimiter {
+            config,
+            counters: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Counts a hit against `key` and returns how long the client has to wait
+    /// when that pushes it over `limit`.
+    pub async fn hit(
+        &self,
+        db: &DatabaseConnection,
+        key: &str,
+        limit: i32,
+        window_seconds: i64,
+    ) -> Result<Option<i64>, DbErr> {
+        let now = Utc::now().timestamp();
+        let window_start = now - now % window_seconds;
+
+        let count = match self.config.store {
+            StoreKind::Memory => self.hit_memory(key, window_start, window_seconds, now),
+            StoreKind::Database => self.hit_database(db, key, window_start).await?,
+        };
+
+        if count > limit {
+            Ok(Some(window_start + window_seconds - now))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn hit_memory(&self, key: &str, window_start: i64, window_seconds: i64, now: i64) -> i32 {
+        let mut counters = self.counters.lock().unwrap();
+
+        if counters.len() > MAX_MEMORY_KEYS {
+            counters.retain(|_, (start, _)| *start + window_seconds > now);
+        }
+
+        let counter = counters.entry(key.to_string()).or_insert((window_start, 0));
+        if counter.0 != window_start {
+            *counter = (window_start, 0);
+        }
+        counter.1 += 1;
+
+        counter.1
+    }
+
+    async fn hit_database(
+        &self,
+        db: &DatabaseConnection,
+        key: &str,
+        window_start: i64,
+    ) -> Result<i32, DbErr> {
+        db.execute(Statement::from_sql_and_values(
+            db.get_database_backend(),
+            COUNT_HIT,
+            vec![key.into(), window_start.into()],
+        ))
+        .await?;
+
+        // -- Read back after counting, so every hit sees at least its own
+        let counter = RateLimits::find()
+            .filter(rate_limits::Column::Key.eq(key))
+            .one(db)
+            .await?;
+
+        match counter {
+            Some(counter) => Ok(counter.count),
+            None => Err(DbErr::Custom(format!(
+                "Rate limit counter {} is missing",
+                key
+            ))),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, G: RouteGroup> FromRequest<'r> for RateLimit<G> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let limiter = req.rocket().state::<RateLimiter>().unwrap();
+
+        let limits = match limiter.config.groups.get(G::NAME) {
+            Some(limits) => limits,
+            None => return Outcome::Success(RateLimit(PhantomData)),
+        };
+
+        let mut buckets = Vec::new();
+
+        if let Some(limit) = limits.per_ip {
+            let ip = req
+                .client_ip()
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+
+            buckets.push((format!("{}:ip:{}", G::NAME, ip), limit));
+        }
+
+        if let Some(limit) = limits.per_wallet {
+            let jwt_keys = req.rocket().state::<JwtKeys>().unwrap();
+            let claims = req
+                .headers()
+                .get_one("Authorization")
+                .and_then(|token| decode_jwt(token, jwt_keys).ok());
+
+            if let Some(claims) = claims {
+                buckets.push((format!("{}:wallet:{}", G::NAME, claims.sub), limit));
+            }
+        }
+
+        let db = &Db::fetch(req.rocket()).unwrap().conn;
+
+        for (key, limit) in buckets {
+            // -- A failing store should not take the API down with it
+            if let Ok(Some(retry_after)) = limiter.hit(db, &key, limit, limits.window_seconds).await
+            {
+                req.local_cache(|| RetryAfter(Some(retry_after)));
+                return Outcome::Failure((Status::TooManyRequests, ()));
+            }
+        }
+
+        Outcome::Success(RateLimit(PhantomData))
+    }
+}
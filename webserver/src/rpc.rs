@@ -0,0 +1,222 @@
+use anyhow::{anyhow, Result};
+use rocket::serde::Deserialize;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{str::FromStr, time::Duration};
+use tokio::sync::Semaphore;
+
+/// `[default.rpc]` section of `Rocket.toml`.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct RpcConfig {
+    /// Endpoints in order of preference. Later ones are only used while the
+    /// earlier ones fail or lag behind.
+    pub urls: Vec<String>,
+    pub timeout_seconds: u64,
+    /// Commitment for metadata reads and writes. Payments are read at
+    /// `payment_commitment`.
+    pub commitment: String,
+    /// Slots an endpoint may trail the highest one before it is skipped.
+    pub max_slot_lag: u64,
+    pub health_check_seconds: u64,
+    /// Chain calls allowed to run at once. Each holds a blocking thread until
+    /// its RPC requests return.
+    pub max_in_flight: usize,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            urls: vec!["https://sol.gibki.io".to_string()],
+            timeout_seconds: 120,
+            commitment: "processed".to_string(),
+            max_slot_lag: 150,
+            health_check_seconds: 30,
+            max_in_flight: 16,
+        }
+    }
+}
+
+struct Endpoint {
+    url: String,
+    client: RpcClient,
+    lagging: AtomicBool,
+}
+
+/// Shared RPC clients for the configured endpoints, managed as Rocket state.
+/// Cloning is cheap and shares the clients.
+#[derive(Clone)]
+pub struct SolanaRpc {
+    endpoints: Arc<Vec<Endpoint>>,
+    permits: Arc<Semaphore>,
+    max_slot_lag: u64,
+}
+
+impl SolanaRpc {
+    pub fn new(config: &RpcConfig) -> Result<Self> {
+        let commitment = parse_commitment(&config.commitment)?;
+        let timeout = Duration::from_secs(config.timeout_seconds);
+
+        if config.urls.is_empty() {
+            return Err(anyhow!("No RPC urls configured"));
+        }
+
+        if config.health_check_seconds == 0 {
+            return Err(anyhow!("RPC health_check_seconds must be positive"));
+        }
+
+        let endpoints = config
+            .urls
+            .iter()
+            .map(|url| Endpoint {
+                url: url.clone(),
+                client: RpcClient::new_with_timeout_and_commitment(
+                    url.clone(),
+                    timeout,
+                    commitment,
+                ),
+                lagging: AtomicBool::new(false),
+            })
+            .collect();
+
+        Ok(SolanaRpc {
+            endpoints: Arc::new(endpoints),
+            permits: Arc::new(Semaphore::new(config.max_in_flight.max(1))),
+            max_slot_lag: config.max_slot_lag,
+        })
+    }
+
+    /// Runs `call` on the blocking pool, waiting for a free slot first so a
+    /// slow RPC cannot tie up more than `max_in_flight` threads. `call` runs
+    /// again on failover, so transactions are signed before it, not inside it.
+    pub async fn call<T, E, F>(&self, call: F) -> Result<T>
+    where
+        T: Send + 'static,
+        E: Into<anyhow::Error>,
+        F: Fn(&RpcClient) -> std::result::Result<T, E> + Send + 'static,
+    {
+        // -- The permit moves into the blocking closure, since the thread keeps
+        // -- running even if the request that started it is dropped
+        let permit = self.permits.clone().acquire_owned().await?;
+        let rpc = self.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            rpc.call_blocking(call)
+        })
+        .await?
+    }
+
+    /// Runs `call` against the endpoints in order, moving on to the next one on
+    /// transport errors or an unhealthy node. Lagging endpoints are only tried
+    /// once every healthy one has failed.
+    fn call_blocking<T, E, F>(&self, call: F) -> Result<T>
+    where
+        E: Into<anyhow::Error>,
+        F: Fn(&RpcClient) -> std::result::Result<T, E>,
+    {
+        let healthy = self
+            .endpoints
+            .iter()
+            .filter(|e| !e.lagging.load(Ordering::Relaxed));
+        let lagging = self
+            .endpoints
+            .iter()
+            .filter(|e| e.lagging.load(Ordering::Relaxed));
+
+        let mut last_error = None;
+
+        for endpoint in healthy.chain(lagging) {
+            let error = match call(&endpoint.client) {
+                Ok(value) => return Ok(value),
+                Err(e) => e.into(),
+            };
+
+            if !should_fail_over(&error) {
+                return Err(error);
+            }
+
+            log::warn!(
+                "RPC {} failed, trying the next endpoint: {}",
+                endpoint.url,
+                error
+            );
+            last_error = Some(error);
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("No RPC endpoints available")))
+    }
+
+    /// Marks endpoints whose slot trails the highest reported one by more than
+    /// `max_slot_lag`, or that do not answer at all.
+    pub fn check_slots(&self) {
+        let slots: Vec<Option<u64>> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.client.get_slot().ok())
+            .collect();
+
+        let highest = slots.iter().flatten().copied().max().unwrap_or(0);
+
+        for (endpoint, slot) in self.endpoints.iter().zip(slots) {
+            let lagging = match slot {
+                Some(slot) => highest.saturating_sub(slot) > self.max_slot_lag,
+                None => true,
+            };
+
+            if lagging != endpoint.lagging.swap(lagging, Ordering::Relaxed) {
+                log::warn!(
+                    "RPC {} is {}",
+                    endpoint.url,
+                    if lagging { "lagging" } else { "caught up" }
+                );
+            }
+        }
+    }
+}
+
+/// Re-checks endpoint slots in the background for as long as the server runs.
+pub async fn run_health_checks(rpc: SolanaRpc, interval_seconds: u64) {
+    if rpc.endpoints.len() < 2 {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+
+    loop {
+        interval.tick().await;
+
+        let rpc = rpc.clone();
+        let _ = tokio::task::spawn_blocking(move || rpc.check_slots()).await;
+    }
+}
+
+pub fn parse_commitment(commitment: &str) -> Result<CommitmentConfig> {
+    match CommitmentLevel::from_str(commitment) {
+        Ok(commitment) => Ok(CommitmentConfig { commitment }),
+        Err(_) => Err(anyhow!("Unknown commitment level {}", commitment)),
+    }
+}
+
+/// Errors another endpoint might not run into: the request never got an
+/// answer, or the node reported itself as behind.
+fn should_fail_over(error: &anyhow::Error) -> bool {
+    let error = match error.chain().find_map(|e| e.downcast_ref::<ClientError>()) {
+        Some(error) => error,
+        None => return false,
+    };
+
+    matches!(
+        error.kind(),
+        ClientErrorKind::Io(_)
+            | ClientErrorKind::Reqwest(_)
+            | ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                data: RpcResponseErrorData::NodeUnhealthy { .. },
+                ..
+            })
+    )
+}
@@ -0,0 +1,143 @@
+use crate::rpc::SolanaRpc;
+use crate::Config;
+use anyhow::{anyhow, Context, Result};
+use rocket::serde::{Deserialize, Deserializer};
+use solana_sdk::signature::{Keypair, Signer};
+use std::{fmt, ops::Deref, sync::Arc};
+use zeroize::Zeroizing;
+
+/// Where a secret is read from. A bare string is the secret itself for tokens
+/// and a file path for keypairs.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SecretSource {
+    Inline(String),
+    Env { env: String },
+    File { path: String },
+}
+
+impl SecretSource {
+    fn env(name: &str) -> Result<Zeroizing<String>> {
+        match std::env::var(name) {
+            Ok(value) => Ok(Zeroizing::new(value)),
+            Err(_) => Err(anyhow!("Environment variable {} is not set", name)),
+        }
+    }
+
+    fn file(path: &str) -> Result<Zeroizing<String>> {
+        let contents = std::fs::read_to_string(path)
+            .map(Zeroizing::new)
+            .with_context(|| format!("Failed to read secret file {}", path))?;
+
+        Ok(Zeroizing::new(contents.trim().to_string()))
+    }
+}
+
+/// A token or password, read from the config, an environment variable or a
+/// file when the config is loaded. Wiped from memory when dropped.
+#[derive(Clone)]
+pub struct Secret(Zeroizing<String>);
+
+impl Deref for Secret {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = match SecretSource::deserialize(deserializer)? {
+            SecretSource::Inline(value) => Ok(Zeroizing::new(value)),
+            SecretSource::Env { env } => SecretSource::env(&env),
+            SecretSource::File { path } => SecretSource::file(&path),
+        };
+
+        value.map(Secret).map_err(rocket::serde::de::Error::custom)
+    }
+}
+
+/// A Solana keypair, read from a file or an environment variable when the
+/// config is loaded. Both the solana-keygen JSON byte array and base58 are
+/// accepted. The secret key is wiped from memory when the last copy drops.
+#[derive(Clone)]
+pub struct SecretKeypair(Arc<Keypair>);
+
+impl SecretKeypair {
+    fn parse(encoded: &str) -> Result<Self> {
+        let bytes = if encoded.starts_with('[') {
+            serde_json::from_str::<Vec<u8>>(encoded).map(Zeroizing::new)?
+        } else {
+            bs58::decode(encoded).into_vec().map(Zeroizing::new)?
+        };
+
+        match Keypair::from_bytes(&bytes) {
+            Ok(keypair) => Ok(SecretKeypair(Arc::new(keypair))),
+            Err(_) => Err(anyhow!("Not a valid keypair")),
+        }
+    }
+}
+
+impl Deref for SecretKeypair {
+    type Target = Keypair;
+
+    fn deref(&self) -> &Keypair {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretKeypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretKeypair({})", self.0.pubkey())
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretKeypair {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (encoded, source) = match SecretSource::deserialize(deserializer)? {
+            SecretSource::Inline(path) | SecretSource::File { path } => {
+                (SecretSource::file(&path), path)
+            }
+            SecretSource::Env { env } => (SecretSource::env(&env), env),
+        };
+
+        encoded
+            .and_then(|encoded| SecretKeypair::parse(&encoded))
+            .map_err(|e| anyhow!("Keypair {}: {}", source, e))
+            .map_err(rocket::serde::de::Error::custom)
+    }
+}
+
+/// Checks at startup that the configured keys can do their job: the update
+/// authority must be the one set on the collection mint's metadata, and the
+/// treasury keypair must belong to the treasury.
+pub async fn check(config: &Config, rpc: &SolanaRpc) -> Result<()> {
+    if let Some(keypair) = &config.treasury_keypair {
+        if keypair.pubkey() != config.treasury.0 {
+            return Err(anyhow!("Treasury keypair does not match the treasury"));
+        }
+    }
+
+    let mint = config.collection_mint;
+    let metadata = rpc
+        .call(move |client| metaboss::decode::decode(client, &mint.to_string()))
+        .await
+        .with_context(|| format!("Failed to fetch metadata of collection mint {}", mint))?;
+
+    if metadata.update_authority != config.update_authority.pubkey() {
+        return Err(anyhow!(
+            "Update authority keypair {} does not match the collection's update authority {}",
+            config.update_authority.pubkey(),
+            metadata.update_authority
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,245 @@
+use super::{http_client, MetadataStorage};
+use crate::secrets::SecretKeypair;
+use anyhow::{anyhow, Result};
+use rand::RngCore;
+use rocket::serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha384};
+use solana_sdk::signature::{Keypair, Signer};
+
+/// ANS-104 signature type of ed25519 (Solana) keys.
+const ED25519_SIGNATURE_TYPE: u16 = 2;
+
+#[derive(Deserialize, Clone)]
+pub struct ArweaveConfig {
+    /// Bundler node, e.g. `https://node1.bundlr.network`.
+    pub bundler_url: String,
+    /// Solana keypair funded on the bundler. It signs every data item.
+    pub keypair: SecretKeypair,
+    /// URI template; `{id}` is replaced with the data item id.
+    #[serde(default = "default_gateway")]
+    pub gateway: String,
+}
+
+fn default_gateway() -> String {
+    "https://arweave.net/{id}".to_string()
+}
+
+/// Posts uploads to Arweave as ANS-104 data items through a bundler.
+pub struct Arweave {
+    config: ArweaveConfig,
+    client: reqwest::Client,
+}
+
+impl Arweave {
+    pub fn new(config: &ArweaveConfig) -> Self {
+        Arweave {
+            config: config.clone(),
+            client: http_client(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl MetadataStorage for Arweave {
+    async fn put(&self, _name: &str, content_type: &str, bytes: Vec<u8>) -> Result<String> {
+        // -- A random anchor keeps identical uploads from getting the same id
+        let mut anchor = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut anchor);
+
+        let item = data_item(
+            &self.config.keypair,
+            &anchor,
+            &[("Content-Type", content_type)],
+            &bytes,
+        );
+
+        let response: Value = self
+            .client
+            .post(format!(
+                "{}/tx/solana",
+                self.config.bundler_url.trim_end_matches('/')
+            ))
+            .header("Content-Type", "application/octet-stream")
+            .body(item)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        match response["id"].as_str() {
+            Some(id) => Ok(self.config.gateway.replace("{id}", id)),
+            None => Err(anyhow!("Bundler returned no data item id")),
+        }
+    }
+}
+
+/// Builds a signed ANS-104 data item without a target.
+fn data_item(keypair: &Keypair, anchor: &[u8; 32], tags: &[(&str, &str)], data: &[u8]) -> Vec<u8> {
+    let owner = keypair.pubkey().to_bytes();
+    let signature_type = ED25519_SIGNATURE_TYPE.to_string();
+    let tag_bytes = encode_tags(tags);
+
+    let fields: [&[u8]; 8] = [
+        b"dataitem",
+        b"1",
+        signature_type.as_bytes(),
+        &owner,
+        &[],
+        anchor,
+        &tag_bytes,
+        data,
+    ];
+    let signature = keypair.sign_message(&deep_hash(&fields));
+
+    let mut item = Vec::with_capacity(data.len() + tag_bytes.len() + 150);
+    item.extend_from_slice(&ED25519_SIGNATURE_TYPE.to_le_bytes());
+    item.extend_from_slice(signature.as_ref());
+    item.extend_from_slice(&owner);
+    // -- No target, then the anchor
+    item.push(0);
+    item.push(1);
+    item.extend_from_slice(anchor);
+    item.extend_from_slice(&(tags.len() as u64).to_le_bytes());
+    item.extend_from_slice(&(tag_bytes.len() as u64).to_le_bytes());
+    item.extend_from_slice(&tag_bytes);
+    item.extend_from_slice(data);
+
+    item
+}
+
+/// Arweave deep hash of a flat list of blobs.
+fn deep_hash(chunks: &[&[u8]]) -> Vec<u8> {
+    let list_tag = format!("list{}", chunks.len());
+
+    chunks
+        .iter()
+        .fold(sha384(list_tag.as_bytes()), |acc, chunk| {
+            let blob_tag = format!("blob{}", chunk.len());
+            let blob = sha384(&[sha384(blob_tag.as_bytes()), sha384(chunk)].concat());
+
+            sha384(&[acc, blob].concat())
+        })
+}
+
+fn sha384(bytes: &[u8]) -> Vec<u8> {
+    Sha384::digest(bytes).to_vec()
+}
+
+/// Avro encoding of the tag array ANS-104 expects.
+fn encode_tags(tags: &[(&str, &str)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    if tags.is_empty() {
+        return bytes;
+    }
+
+    write_long(&mut bytes, tags.len() as i64);
+    for (name, value) in tags {
+        write_long(&mut bytes, name.len() as i64);
+        bytes.extend_from_slice(name.as_bytes());
+        write_long(&mut bytes, value.len() as i64);
+        bytes.extend_from_slice(value.as_bytes());
+    }
+    write_long(&mut bytes, 0);
+
+    bytes
+}
+
+/// Zigzag varint, as Avro writes `long`s.
+fn write_long(bytes: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::content_hash;
+    use sha2::Sha256;
+    use solana_sdk::signature::{keypair_from_seed, Signature};
+
+    #[test]
+    fn writes_longs_as_avro_zigzag_varints() {
+        // -- Examples from the Avro specification
+        for (value, expected) in [
+            (0, vec![0x00]),
+            (-1, vec![0x01]),
+            (1, vec![0x02]),
+            (-2, vec![0x03]),
+            (2, vec![0x04]),
+            (-64, vec![0x7f]),
+            (64, vec![0x80, 0x01]),
+        ] {
+            let mut bytes = Vec::new();
+            write_long(&mut bytes, value);
+            assert_eq!(bytes, expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn encodes_tags_as_an_avro_array() {
+        assert_eq!(
+            hex::encode(encode_tags(&[("Content-Type", "text/plain")])),
+            "0218436f6e74656e742d5479706514746578742f706c61696e00"
+        );
+        assert!(encode_tags(&[]).is_empty());
+    }
+
+    // -- Expected values below were computed with an independent
+    // -- implementation of arweave-js `deepHash` and the arbundles layout
+
+    #[test]
+    fn deep_hashes_a_list_of_blobs() {
+        let chunks: [&[u8]; 3] = [b"dataitem", b"1", b""];
+
+        assert_eq!(
+            hex::encode(deep_hash(&chunks)),
+            "94a8f1d37a6e0df71402d235634f526b15a7f2585aeb7731436d8be760d56fade9cc03a60d779b202e9c191291ced8c3"
+        );
+    }
+
+    #[test]
+    fn builds_a_known_data_item() {
+        let keypair = keypair_from_seed(&[7; 32]).unwrap();
+        let anchor = [1; 32];
+        let data = br#"{"name":"Shinobi #1"}"#;
+        let tags = [("Content-Type", "application/json")];
+
+        let item = data_item(&keypair, &anchor, &tags, data);
+
+        assert_eq!(item.len(), 201);
+        assert_eq!(
+            content_hash(&item),
+            "6eef70c2c19a805142e5279ec5ad25edc03a179d85edb518c9d8af277b29ee04"
+        );
+
+        // -- The id is the SHA-256 of the signature, after the signature type
+        let signature = &item[2..66];
+        let id = base64::encode_config(Sha256::digest(signature), base64::URL_SAFE_NO_PAD);
+        assert_eq!(id, "45Q4Lae6SaBsnUoH6GBW5BhBUFK_6A1JyLxBCqYP7so");
+
+        // -- The owner follows the signature, and signs the ANS-104 fields
+        let owner = keypair.pubkey().to_bytes();
+        let tag_bytes = encode_tags(&tags);
+        let fields: [&[u8]; 8] = [
+            b"dataitem",
+            b"1",
+            b"2",
+            &owner,
+            &[],
+            &anchor,
+            &tag_bytes,
+            data,
+        ];
+
+        assert_eq!(&item[66..98], &owner);
+        assert!(Signature::new(signature).verify(&owner, &deep_hash(&fields)));
+    }
+}
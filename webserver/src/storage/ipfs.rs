@@ -0,0 +1,65 @@
+use super::{http_client, MetadataStorage};
+use crate::secrets::Secret;
+use anyhow::{anyhow, Result};
+use reqwest::multipart::{Form, Part};
+use rocket::serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize, Clone)]
+pub struct IpfsConfig {
+    /// Base URL of the node's HTTP RPC API, e.g. `http://127.0.0.1:5001`.
+    pub api_url: String,
+    /// URI template; `{cid}` is replaced with the uploaded content's CID.
+    #[serde(default = "default_gateway")]
+    pub gateway: String,
+    /// Sent as a bearer token for nodes behind an authenticating proxy.
+    pub token: Option<Secret>,
+}
+
+fn default_gateway() -> String {
+    "https://ipfs.io/ipfs/{cid}".to_string()
+}
+
+/// Adds and pins uploads on an IPFS node through its HTTP API.
+pub struct Ipfs {
+    config: IpfsConfig,
+    client: reqwest::Client,
+}
+
+impl Ipfs {
+    pub fn new(config: &IpfsConfig) -> Self {
+        Ipfs {
+            config: config.clone(),
+            client: http_client(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl MetadataStorage for Ipfs {
+    async fn put(&self, name: &str, content_type: &str, bytes: Vec<u8>) -> Result<String> {
+        let part = Part::bytes(bytes)
+            .file_name(name.to_string())
+            .mime_str(content_type)?;
+        let form = Form::new().part("file", part);
+
+        let mut request = self
+            .client
+            .post(format!(
+                "{}/api/v0/add?pin=true&cid-version=1",
+                self.config.api_url.trim_end_matches('/')
+            ))
+            .multipart(form);
+
+        if let Some(token) = &self.config.token {
+            request = request.bearer_auth(&**token);
+        }
+
+        let response: Value = request.send().await?.error_for_status()?.json().await?;
+
+        match response["Hash"].as_str() {
+            Some(cid) => Ok(self.config.gateway.replace("{cid}", cid)),
+            None => Err(anyhow!("IPFS node returned no hash")),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use super::{content_hash, MetadataStorage};
+use anyhow::Result;
+use rocket::serde::Deserialize;
+use std::path::PathBuf;
+
+#[derive(Deserialize, Clone)]
+pub struct LocalConfig {
+    /// Directory uploads are written under.
+    pub path: PathBuf,
+    /// URL `path` is served from; uploads get `file://` URIs without one.
+    pub base_url: Option<String>,
+}
+
+/// Writes uploads to the local filesystem, for development and tests.
+pub struct Local {
+    config: LocalConfig,
+}
+
+impl Local {
+    pub fn new(config: &LocalConfig) -> Self {
+        Local {
+            config: config.clone(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl MetadataStorage for Local {
+    async fn put(&self, name: &str, _content_type: &str, bytes: Vec<u8>) -> Result<String> {
+        let key = format!("{}/{}", content_hash(&bytes), name);
+        let path = self.config.path.join(&key);
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, bytes).await?;
+
+        match &self.config.base_url {
+            Some(base_url) => Ok(format!("{}/{}", base_url.trim_end_matches('/'), key)),
+            None => Ok(format!(
+                "file://{}",
+                tokio::fs::canonicalize(&path).await?.display()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn scratch_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("metamutate-local-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn put_json_round_trips_through_base_url() {
+        let dir = scratch_dir();
+        let local = Local::new(&LocalConfig {
+            path: dir.clone(),
+            base_url: Some("http://127.0.0.1:8000/metadata/".to_string()),
+        });
+        let value = json!({
+            "name": "Shinobi #1",
+            "attributes": [{ "trait_type": "Rank", "value": "Jonin" }]
+        });
+
+        let uri = local.put_json("mint.json", &value).await.unwrap();
+
+        let key = uri
+            .strip_prefix("http://127.0.0.1:8000/metadata/")
+            .expect("URI is served from base_url");
+        let stored = std::fs::read(dir.join(key)).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&stored).unwrap(), value);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn put_returns_distinct_file_uris_for_distinct_content() {
+        let dir = scratch_dir();
+        let local = Local::new(&LocalConfig {
+            path: dir.clone(),
+            base_url: None,
+        });
+
+        let first = local
+            .put("mint.json", "application/json", b"{}".to_vec())
+            .await
+            .unwrap();
+        let second = local
+            .put("mint.json", "application/json", b"[]".to_vec())
+            .await
+            .unwrap();
+
+        assert_ne!(first, second);
+        for (uri, bytes) in [(&first, b"{}"), (&second, b"[]")] {
+            let path = uri.strip_prefix("file://").expect("URI is a file URI");
+            assert_eq!(std::fs::read(path).unwrap(), bytes);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use rocket::serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, time::Duration};
+
+pub mod arweave;
+pub mod ipfs;
+pub mod local;
+pub mod nft_storage;
+pub mod s3;
+
+/// Somewhere rank-up metadata can be published to. Every upload gets its own
+/// URI, which keeps resolving to the same content.
+#[rocket::async_trait]
+pub trait MetadataStorage: Send + Sync {
+    /// Stores `bytes` under `name` and returns their permanent URI.
+    async fn put(&self, name: &str, content_type: &str, bytes: Vec<u8>) -> Result<String>;
+
+    async fn put_json(&self, name: &str, value: &Value) -> Result<String> {
+        self.put(name, "application/json", serde_json::to_vec(value)?)
+            .await
+    }
+}
+
+/// One storage backend, picked by `kind`.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StorageConfig {
+    NftStorage(nft_storage::NftStorageConfig),
+    Ipfs(ipfs::IpfsConfig),
+    S3(s3::S3Config),
+    Arweave(arweave::ArweaveConfig),
+    Local(local::LocalConfig),
+}
+
+impl StorageConfig {
+    pub fn backend(&self) -> Box<dyn MetadataStorage> {
+        match self {
+            StorageConfig::NftStorage(config) => Box::new(nft_storage::NftStorage::new(config)),
+            StorageConfig::Ipfs(config) => Box::new(ipfs::Ipfs::new(config)),
+            StorageConfig::S3(config) => Box::new(s3::S3::new(config)),
+            StorageConfig::Arweave(config) => Box::new(arweave::Arweave::new(config)),
+            StorageConfig::Local(config) => Box::new(local::Local::new(config)),
+        }
+    }
+}
+
+/// `[default.storage]` section of `Rocket.toml`. Collections, keyed by their
+/// verified collection mint, can publish somewhere other than `default`.
+#[derive(Deserialize, Clone)]
+pub struct StorageSettings {
+    pub default: StorageConfig,
+    #[serde(default)]
+    pub collections: HashMap<String, StorageConfig>,
+}
+
+impl StorageSettings {
+    pub fn for_collection(&self, collection: Option<&str>) -> &StorageConfig {
+        collection
+            .and_then(|collection| self.collections.get(collection))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Longest an upload may take, so a stalled backend cannot hold a rank-up job
+/// past its lock.
+const UPLOAD_TIMEOUT_SECONDS: u64 = 60;
+
+/// HTTP client for the backends' uploads.
+pub fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(UPLOAD_TIMEOUT_SECONDS))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+/// Hex SHA-256 of `bytes`, used by backends that pick their own object keys so
+/// new content never overwrites a published URI.
+pub fn content_hash(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
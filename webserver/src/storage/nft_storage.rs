@@ -0,0 +1,69 @@
+use super::{http_client, MetadataStorage};
+use crate::secrets::Secret;
+use anyhow::{anyhow, Result};
+use rocket::serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize, Clone)]
+pub struct NftStorageConfig {
+    pub token: Secret,
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    /// URI template; `{cid}` is replaced with the uploaded content's CID.
+    #[serde(default = "default_gateway")]
+    pub gateway: String,
+}
+
+fn default_api_url() -> String {
+    "https://api.nft.storage".to_string()
+}
+
+fn default_gateway() -> String {
+    "https://{cid}.ipfs.nftstorage.link".to_string()
+}
+
+/// Pins uploads through the nft.storage API.
+pub struct NftStorage {
+    config: NftStorageConfig,
+    client: reqwest::Client,
+}
+
+impl NftStorage {
+    pub fn new(config: &NftStorageConfig) -> Self {
+        NftStorage {
+            config: config.clone(),
+            client: http_client(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl MetadataStorage for NftStorage {
+    async fn put(&self, _name: &str, content_type: &str, bytes: Vec<u8>) -> Result<String> {
+        let response: Value = self
+            .client
+            .post(format!(
+                "{}/upload",
+                self.config.api_url.trim_end_matches('/')
+            ))
+            .bearer_auth(&*self.config.token)
+            .header("Content-Type", content_type)
+            .body(bytes)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if response["ok"].as_bool() != Some(true) {
+            return Err(anyhow!(
+                "nft.storage upload unsuccessful: {}",
+                response["error"]
+            ));
+        }
+
+        match response["value"]["cid"].as_str() {
+            Some(cid) => Ok(self.config.gateway.replace("{cid}", cid)),
+            None => Err(anyhow!("nft.storage cid is missing")),
+        }
+    }
+}
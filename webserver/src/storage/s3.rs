@@ -0,0 +1,268 @@
+use super::{content_hash, http_client, MetadataStorage};
+use crate::secrets::Secret;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::Url;
+use rocket::serde::Deserialize;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+/// Characters SigV4 leaves unescaped in a path segment.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Deserialize, Clone)]
+pub struct S3Config {
+    /// Service endpoint, e.g. `https://s3.eu-central-1.amazonaws.com` or the
+    /// URL of an S3-compatible store. Buckets are addressed path-style.
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: Secret,
+    /// Prepended to every object key.
+    #[serde(default)]
+    pub prefix: String,
+    /// Public URL the bucket's objects are read from.
+    pub public_url: String,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// Uploads objects to an S3-compatible bucket with SigV4-signed requests.
+pub struct S3 {
+    config: S3Config,
+    client: reqwest::Client,
+}
+
+impl S3 {
+    pub fn new(config: &S3Config) -> Self {
+        S3 {
+            config: config.clone(),
+            client: http_client(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl MetadataStorage for S3 {
+    async fn put(&self, name: &str, content_type: &str, bytes: Vec<u8>) -> Result<String> {
+        let payload_hash = content_hash(&bytes);
+        let key = format!("{}{}/{}", self.config.prefix, payload_hash, name);
+        let canonical_uri = canonical_uri(&format!("{}/{}", self.config.bucket, key));
+
+        let url = Url::parse(&format!(
+            "{}{}",
+            self.config.endpoint.trim_end_matches('/'),
+            canonical_uri
+        ))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(anyhow!("S3 endpoint has no host")),
+        };
+
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let headers = [
+            ("content-type", content_type),
+            ("host", host.as_str()),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+
+        let signature = sign(
+            &self.config.secret_access_key,
+            &self.config.region,
+            &amz_date,
+            "PUT",
+            &canonical_uri,
+            &headers,
+            &payload_hash,
+        )?;
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.config.access_key_id,
+            scope(&amz_date, &self.config.region),
+            signed_headers(&headers),
+            signature
+        );
+
+        self.client
+            .put(url)
+            .header("Content-Type", content_type)
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", &amz_date)
+            .header("Authorization", authorization)
+            .body(bytes)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(format!(
+            "{}/{}",
+            self.config.public_url.trim_end_matches('/'),
+            key
+        ))
+    }
+}
+
+/// Escapes each segment of an object path the way SigV4 canonicalizes it.
+fn canonical_uri(path: &str) -> String {
+    let segments: Vec<String> = path
+        .split('/')
+        .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+        .collect();
+
+    format!("/{}", segments.join("/"))
+}
+
+fn scope(amz_date: &str, region: &str) -> String {
+    format!("{}/{}/s3/aws4_request", &amz_date[..8], region)
+}
+
+fn signed_headers(headers: &[(&str, &str)]) -> String {
+    let names: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+    names.join(";")
+}
+
+/// SigV4 signature of an S3 request without a query string. `headers` are
+/// lowercase, sorted by name and all signed.
+fn sign(
+    secret_access_key: &str,
+    region: &str,
+    amz_date: &str,
+    method: &str,
+    canonical_uri: &str,
+    headers: &[(&str, &str)],
+    payload_hash: &str,
+) -> Result<String> {
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method,
+        canonical_uri,
+        canonical_headers,
+        signed_headers(headers),
+        payload_hash
+    );
+
+    let scope = scope(amz_date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        content_hash(canonical_request.as_bytes())
+    );
+
+    let secret = Zeroizing::new(format!("AWS4{}", secret_access_key));
+    let signing_key = [&amz_date[..8], region, "s3", "aws4_request"]
+        .iter()
+        .try_fold(secret.as_bytes().to_vec(), |key, part| {
+            hmac(&key, part.as_bytes())
+        })?;
+
+    Ok(hex::encode(hmac(&signing_key, string_to_sign.as_bytes())?))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key).map_err(|_| anyhow!("Invalid S3 signing key"))?;
+    mac.update(data);
+
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // -- Example requests and credentials from the AWS documentation,
+    // -- "Signature Calculations for the Authorization Header: Transferring
+    // -- Payload in a Single Chunk"
+    const SECRET_ACCESS_KEY: &str = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+    const AMZ_DATE: &str = "20130524T000000Z";
+    const HOST: &str = "examplebucket.s3.amazonaws.com";
+
+    #[test]
+    fn signs_the_aws_put_object_example() {
+        let payload_hash = content_hash(b"Welcome to Amazon S3.");
+        assert_eq!(
+            payload_hash,
+            "44ce7dd67c959e0d3524ffac1771dfbba87d2b6b4b4e99e42034a8b803f8b072"
+        );
+
+        let headers = [
+            ("date", "Fri, 24 May 2013 00:00:00 GMT"),
+            ("host", HOST),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", AMZ_DATE),
+            ("x-amz-storage-class", "REDUCED_REDUNDANCY"),
+        ];
+        let signature = sign(
+            SECRET_ACCESS_KEY,
+            "us-east-1",
+            AMZ_DATE,
+            "PUT",
+            &canonical_uri("test$file.text"),
+            &headers,
+            &payload_hash,
+        )
+        .unwrap();
+
+        assert_eq!(
+            signed_headers(&headers),
+            "date;host;x-amz-content-sha256;x-amz-date;x-amz-storage-class"
+        );
+        assert_eq!(
+            signature,
+            "98ad721746da40c64f1a55b78f14c238d841ea1380cd77a1b5971af0ece108bd"
+        );
+    }
+
+    #[test]
+    fn signs_the_aws_get_object_example() {
+        let payload_hash = content_hash(b"");
+        let headers = [
+            ("host", HOST),
+            ("range", "bytes=0-9"),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", AMZ_DATE),
+        ];
+        let signature = sign(
+            SECRET_ACCESS_KEY,
+            "us-east-1",
+            AMZ_DATE,
+            "GET",
+            &canonical_uri("test.txt"),
+            &headers,
+            &payload_hash,
+        )
+        .unwrap();
+
+        assert_eq!(
+            signature,
+            "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41"
+        );
+    }
+
+    #[test]
+    fn canonical_uri_escapes_each_segment() {
+        assert_eq!(canonical_uri("test$file.text"), "/test%24file.text");
+        assert_eq!(
+            canonical_uri("bucket/prefix/Shinobi #1.json"),
+            "/bucket/prefix/Shinobi%20%231.json"
+        );
+    }
+}
@@ -1,16 +1,83 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use super::AuthRequest;
 use ed25519_dalek::{PublicKey, Verifier, Signature};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use solana_sdk::{pubkey::Pubkey, sanitize::Sanitize, transaction::Transaction};
 
-pub fn verify_message (request: &AuthRequest, nonce: &str) -> Result<bool> {
-    let signature_base58 = bs58::decode(request.signature).into_vec()?;
-    let pubkey_base58 = bs58::decode(request.pubkey).into_vec()?;
+pub fn verify_message (request: &AuthRequest, message: &str) -> Result<bool> {
+    let signature = match request.signature {
+        Some(signature) => signature,
+        None => return Err(anyhow!("Signature is missing"))
+    };
 
-    let public_key = PublicKey::from_bytes(&pubkey_base58)?;
-    let signature = Signature::from_bytes(&signature_base58)?;
+    let public_key = PublicKey::from_bytes(request.pubkey.0.as_ref())?;
+    let signature = Signature::from_bytes(signature.0.as_ref())?;
 
-    match public_key.verify(nonce.as_bytes(), &signature) {
+    match public_key.verify(message.as_bytes(), &signature) {
         Ok(_) => Ok(true),
         Err(e) => Err(anyhow::anyhow!(e))
     }
 }
+
+/// Verifies a signed (but never submitted) transaction used as a sign-in proof
+/// by wallets that cannot sign arbitrary messages. The transaction may only
+/// carry memo instructions, must be paid for by `pubkey`, and the memo holds
+/// the nonce, which is returned.
+pub fn verify_transaction (pubkey: &Pubkey, transaction: &str) -> Result<String> {
+    let bytes = bs58::decode(transaction).into_vec()?;
+    let transaction: Transaction = bincode::deserialize(&bytes)?;
+    transaction.sanitize()?;
+    let message = &transaction.message;
+
+    let fee_payer = match message.account_keys.first() {
+        Some(fee_payer) => fee_payer,
+        None => return Err(anyhow!("Transaction has no fee payer"))
+    };
+
+    if fee_payer != pubkey {
+        return Err(anyhow!("Transaction fee payer does not match pubkey"));
+    }
+
+    if message.header.num_required_signatures != 1 || transaction.signatures.len() != 1 {
+        return Err(anyhow!("Transaction must only be signed by the fee payer"));
+    }
+
+    // -- `Transaction::verify` skips missing signatures, so check the fee payer's
+    // -- signature itself
+    if !transaction.signatures[0].verify(fee_payer.as_ref(), &message.serialize()) {
+        return Err(anyhow!("Transaction is not signed by the fee payer"));
+    }
+
+    let mut memos = Vec::new();
+    for (index, instruction) in message.instructions.iter().enumerate() {
+        match message.program_id(index) {
+            Some(program_id) if *program_id == spl_memo::id() || *program_id == spl_memo::v1::id() => {
+                memos.push(String::from_utf8(instruction.data.clone())?);
+            },
+            _ => return Err(anyhow!("Transaction may only contain memo instructions"))
+        }
+    }
+
+    let nonce = match memos.as_slice() {
+        [nonce] => nonce.trim().to_string(),
+        _ => return Err(anyhow!("Transaction must contain exactly one memo"))
+    };
+
+    Ok(nonce)
+}
+
+/// Verifies the hex-encoded HMAC-SHA256 an integration computed over
+/// `"{timestamp}.{body}"` with its shared secret.
+pub fn verify_webhook_signature (secret: &str, timestamp: &str, body: &[u8], signature: &str) -> Result<()> {
+    let signature = hex::decode(signature)?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|_| anyhow!("Invalid webhook secret"))?;
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    mac.verify_slice(&signature)
+        .map_err(|_| anyhow!("Webhook signature does not match"))
+}
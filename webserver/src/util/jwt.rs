@@ -0,0 +1,240 @@
+use anyhow::{anyhow, Result};
+use ed25519_dalek::{PublicKey, SecretKey};
+use jsonwebtoken::{DecodingKey, EncodingKey};
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// DER of the Ed25519 algorithm identifier (OID 1.3.101.112).
+const ED25519_ALGORITHM: [u8; 7] = [0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70];
+
+const DER_INTEGER: u8 = 0x02;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OCTET_STRING: u8 = 0x04;
+const DER_SEQUENCE: u8 = 0x30;
+/// `[1] publicKey` of a PKCS#8 v2 (OneAsymmetricKey) private key.
+const DER_PUBLIC_KEY: u8 = 0x81;
+
+/// An Ed25519 key as configured in `jwt_keys`. Entries with a private key can
+/// sign; entries with only a public key are kept around to verify tokens
+/// issued before a rotation.
+#[derive(Deserialize)]
+pub struct JwtKeyConfig {
+    pub kid: String,
+    pub private_key: Option<String>,
+    pub public_key: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Jwk {
+    pub kty: &'static str,
+    pub crv: &'static str,
+    pub alg: &'static str,
+    #[serde(rename = "use")]
+    pub key_use: &'static str,
+    pub kid: String,
+    pub x: String,
+}
+
+pub struct JwtKeys {
+    pub signing_kid: String,
+    signing_key: EncodingKey,
+    verifying_keys: HashMap<String, DecodingKey>,
+    pub jwks: Vec<Jwk>,
+}
+
+impl JwtKeys {
+    pub fn load(keys: &[JwtKeyConfig], signing_kid: &str) -> Result<Self> {
+        let mut signing_key = None;
+        let mut verifying_keys = HashMap::new();
+        let mut jwks = Vec::new();
+
+        for key in keys {
+            let public_key = match (&key.private_key, &key.public_key) {
+                (Some(path), public_path) => {
+                    let pem = std::fs::read(path)?;
+                    let public_key = private_key_public(&pem_to_der(&pem)?)
+                        .map_err(|e| anyhow!("JWT key {}: {}", key.kid, e))?;
+
+                    if let Some(public_path) = public_path {
+                        let configured = public_key_raw(&pem_to_der(&std::fs::read(public_path)?)?)
+                            .map_err(|e| anyhow!("JWT key {}: {}", key.kid, e))?;
+
+                        if configured != public_key {
+                            return Err(anyhow!(
+                                "JWT key {} has a public key that does not match its private key",
+                                key.kid
+                            ));
+                        }
+                    }
+
+                    if key.kid == signing_kid {
+                        signing_key = Some(EncodingKey::from_ed_pem(&pem)?);
+                    }
+
+                    public_key.to_vec()
+                }
+                (None, Some(path)) => public_key_raw(&pem_to_der(&std::fs::read(path)?)?)
+                    .map_err(|e| anyhow!("JWT key {}: {}", key.kid, e))?
+                    .to_vec(),
+                (None, None) => return Err(anyhow!("JWT key {} has no key file", key.kid)),
+            };
+
+            verifying_keys.insert(key.kid.clone(), DecodingKey::from_ed_der(&public_key));
+            jwks.push(Jwk {
+                kty: "OKP",
+                crv: "Ed25519",
+                alg: "EdDSA",
+                key_use: "sig",
+                kid: key.kid.clone(),
+                x: base64::encode_config(&public_key, base64::URL_SAFE_NO_PAD),
+            });
+        }
+
+        let signing_key = match signing_key {
+            Some(signing_key) => signing_key,
+            None => return Err(anyhow!("No private key configured for kid {}", signing_kid)),
+        };
+
+        Ok(JwtKeys {
+            signing_kid: signing_kid.to_string(),
+            signing_key,
+            verifying_keys,
+            jwks,
+        })
+    }
+
+    pub fn signing_key(&self) -> &EncodingKey {
+        &self.signing_key
+    }
+
+    pub fn verifying_key(&self, kid: &str) -> Option<&DecodingKey> {
+        self.verifying_keys.get(kid)
+    }
+}
+
+/// Derives the public key of a PKCS#8 Ed25519 private key. A v2 key that
+/// carries its public key has to carry the matching one.
+fn private_key_public(der: &[u8]) -> Result<[u8; 32]> {
+    let (tag, key, _) = der_element(der)?;
+    let (version_tag, version, rest) = der_element(key)?;
+    let (algorithm, rest) = rest.split_at(rest.len().min(ED25519_ALGORITHM.len()));
+
+    if tag != DER_SEQUENCE
+        || version_tag != DER_INTEGER
+        || !(version == [0] || version == [1])
+        || algorithm != ED25519_ALGORITHM
+    {
+        return Err(anyhow!("not a PKCS#8 Ed25519 private key"));
+    }
+
+    // -- The private key is an OCTET STRING wrapping the 32 byte seed
+    let (wrapper_tag, wrapped, mut rest) = der_element(rest)?;
+    let (seed_tag, seed, _) = der_element(wrapped)?;
+
+    if wrapper_tag != DER_OCTET_STRING || seed_tag != DER_OCTET_STRING || seed.len() != 32 {
+        return Err(anyhow!("not a PKCS#8 Ed25519 private key"));
+    }
+
+    let public_key = PublicKey::from(&SecretKey::from_bytes(seed)?).to_bytes();
+
+    // -- Attributes are skipped; an embedded public key must match
+    while !rest.is_empty() {
+        let (tag, contents, next) = der_element(rest)?;
+
+        if tag == DER_PUBLIC_KEY && contents.get(1..) != Some(&public_key[..]) {
+            return Err(anyhow!(
+                "embedded public key does not match the private key"
+            ));
+        }
+
+        rest = next;
+    }
+
+    Ok(public_key)
+}
+
+/// Reads the raw key of an SPKI Ed25519 public key.
+fn public_key_raw(der: &[u8]) -> Result<[u8; 32]> {
+    let (tag, key, _) = der_element(der)?;
+    let (algorithm, rest) = key.split_at(key.len().min(ED25519_ALGORITHM.len()));
+    let (bits_tag, bits, _) = der_element(rest)?;
+
+    match (tag, algorithm, bits_tag, bits) {
+        (DER_SEQUENCE, algorithm, DER_BIT_STRING, [0, raw @ ..])
+            if algorithm == ED25519_ALGORITHM && raw.len() == 32 =>
+        {
+            let mut public_key = [0u8; 32];
+            public_key.copy_from_slice(raw);
+            Ok(public_key)
+        }
+        _ => Err(anyhow!("not an SPKI Ed25519 public key")),
+    }
+}
+
+/// Splits the first DER element off `der` into its tag, its contents and the
+/// bytes after it. Ed25519 keys never need more than one length byte.
+fn der_element(der: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+    let (tag, length, header) = match der {
+        [tag, length, ..] if *length < 0x80 => (*tag, *length as usize, 2),
+        [tag, 0x81, length, ..] => (*tag, *length as usize, 3),
+        _ => return Err(anyhow!("malformed DER")),
+    };
+
+    match der.get(header..header + length) {
+        Some(contents) => Ok((tag, contents, &der[header + length..])),
+        None => Err(anyhow!("truncated DER")),
+    }
+}
+
+fn pem_to_der(pem: &[u8]) -> Result<Vec<u8>> {
+    let pem = std::str::from_utf8(pem)?;
+    let body: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .map(str::trim)
+        .collect();
+
+    Ok(base64::decode(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // -- Example keys from RFC 8410, section 10
+    const PUBLIC_KEY: &str = "19bf44096984cdfe8541bac167dc3b96c85086aa30b6b6cb0c5c38ad703166e1";
+
+    #[test]
+    fn derives_the_public_key_of_a_v1_private_key() {
+        let der =
+            base64::decode("MC4CAQAwBQYDK2VwBCIEINTuctv5E1hK1bbY8fdp+K06/nwoy/HU++CXqI9EdVhC")
+                .unwrap();
+
+        assert_eq!(hex::encode(private_key_public(&der).unwrap()), PUBLIC_KEY);
+    }
+
+    #[test]
+    fn checks_the_embedded_public_key_of_a_v2_private_key() {
+        let mut der = base64::decode(
+            "MHICAQEwBQYDK2VwBCIEINTuctv5E1hK1bbY8fdp+K06/nwoy/HU++CXqI9EdVhC\
+             oB8wHQYKKoZIhvcNAQkJFDEPDA1DdXJkbGUgQ2hhaXJzgSEAGb9ECWmEzf6FQbrB\
+             Z9w7lshQhqowtrbLDFw4rXAxZuE=",
+        )
+        .unwrap();
+
+        assert_eq!(hex::encode(private_key_public(&der).unwrap()), PUBLIC_KEY);
+
+        let last = der.len() - 1;
+        der[last] ^= 1;
+        assert!(private_key_public(&der).is_err());
+    }
+
+    #[test]
+    fn reads_an_spki_public_key() {
+        let der =
+            base64::decode("MCowBQYDK2VwAyEAGb9ECWmEzf6FQbrBZ9w7lshQhqowtrbLDFw4rXAxZuE=").unwrap();
+
+        assert_eq!(hex::encode(public_key_raw(&der).unwrap()), PUBLIC_KEY);
+        assert!(private_key_public(&der).is_err());
+    }
+}
@@ -1,52 +1,144 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, Header, Validation};
+use jwt::JwtKeys;
+use params::{Base58Pubkey, Base58Signature};
 use rocket::{
     http::Status,
     serde::{json::Json, Deserialize, Serialize},
 };
 use serde_json::Value;
+use siws::SiwsMessage;
+use uuid::Uuid;
 
 pub mod crypto;
+pub mod jwt;
+pub mod params;
+pub mod siws;
 
-pub struct ApiKey<'r>(pub &'r str);
+pub struct ApiKey<'r> {
+    pub token: &'r str,
+    pub claims: Claims,
+}
+
+impl ApiKey<'_> {
+    /// Wallet the token was issued to.
+    pub fn pubkey(&self) -> &str {
+        &self.claims.sub
+    }
+
+    /// Operators and admins may act on any wallet's records.
+    pub fn owns(&self, account: &str) -> bool {
+        self.pubkey() == account || self.claims.role.is_operator()
+    }
+}
+
+pub struct OperatorKey<'r>(pub ApiKey<'r>);
+
+pub struct AdminKey<'r>(pub ApiKey<'r>);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    pub fn is_operator(&self) -> bool {
+        matches!(self, Role::Operator | Role::Admin)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        matches!(self, Role::Admin)
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    pub jti: String,
+    #[serde(default)]
+    pub role: Role,
+}
+
+/// JSON body authenticated with an integration's HMAC signature.
+pub struct SignedPayload<T> {
+    pub integration: String,
+    pub payload: T,
+}
+
+#[derive(Debug)]
+pub enum WebhookError {
+    Missing,
+    Expired,
+    Invalid,
+    Malformed,
 }
 
 #[derive(Debug)]
 pub enum ApiKeyError {
     Missing,
     Invalid,
+    Revoked,
+    Forbidden,
 }
 
 // Requests
 
 #[derive(Deserialize)]
-pub struct AuthRequest<'a> {
-    pub pubkey: &'a str,
-    pub signature: &'a str,
+pub struct AuthRequest {
+    pub pubkey: Base58Pubkey,
+    pub signature: Option<Base58Signature>,
+    pub message: Option<String>,
+    pub transaction: Option<String>,
 }
 
-#[derive(Deserialize)]
-pub struct TaskCreate<'a> {
-    pub mint_address: &'a str,
-    pub account: &'a str
+/// What the wallet signed to prove it owns `pubkey`.
+pub enum SignInProof {
+    Message(SiwsMessage),
+    /// Nonce carried in the memo of a signed transaction.
+    Transaction(String),
+}
+
+impl SignInProof {
+    pub fn nonce(&self) -> &str {
+        match self {
+            SignInProof::Message(message) => &message.nonce,
+            SignInProof::Transaction(nonce) => nonce,
+        }
+    }
 }
 
 #[derive(Deserialize)]
-pub struct PaymentCreate<'a> {
+pub struct RefreshRequest<'a> {
+    pub refresh_token: &'a str,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TaskCreate {
+    pub mint_address: Base58Pubkey,
+    pub account: Base58Pubkey
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PaymentCreate {
     pub task_id: i32,
-    pub account: &'a str
+    pub account: Base58Pubkey
 }
 
 #[derive(Deserialize)]
-pub struct PaymentReceive<'a> {
+pub struct PaymentReceive {
     pub payment_id: i32,
-    pub tx_id: &'a str
+    pub tx_id: Base58Signature
+}
+
+#[derive(Deserialize)]
+pub struct IntegrationCreate<'a> {
+    pub name: &'a str,
 }
 
 // Responses
@@ -60,26 +152,39 @@ pub type WebResponse = (Status, Json<SysResponse>);
 
 // Utility Functions
 
-pub fn create_jwt(pubkey: &str, secret: &str) -> Result<String> {
+pub fn create_jwt(pubkey: &str, role: Role, keys: &JwtKeys, ttl_minutes: i64) -> Result<String> {
     let expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::minutes(30))
+        .checked_add_signed(chrono::Duration::minutes(ttl_minutes))
         .expect("valid timestamp")
         .timestamp();
 
     let claims = Claims {
         sub: pubkey.to_owned(),
         exp: expiration as usize,
+        jti: Uuid::new_v4().to_string(),
+        role,
     };
 
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )?;
+    let mut header = Header::new(Algorithm::EdDSA);
+    header.kid = Some(keys.signing_kid.clone());
+
+    let token = encode(&header, &claims, keys.signing_key())?;
     Ok(token)
 }
 
-pub fn decode_jwt(token: &str, secret: &str) -> Result<()> {
-    decode::<Claims>(token, &DecodingKey::from_secret(secret.as_ref()), &Validation::new(Algorithm::HS256))?;
-    Ok(())
-}
\ No newline at end of file
+pub fn decode_jwt(token: &str, keys: &JwtKeys) -> Result<Claims> {
+    let header = decode_header(token)?;
+
+    let key = match header.kid.as_deref().and_then(|kid| keys.verifying_key(kid)) {
+        Some(key) => key,
+        None => return Err(anyhow!("Token was signed with an unknown key")),
+    };
+
+    let data = decode::<Claims>(token, key, &Validation::new(Algorithm::EdDSA))?;
+    Ok(data.claims)
+}
+
+/// Whether a query failed because it would have broken a unique index.
+pub fn is_unique_violation(error: &sea_orm::DbErr) -> bool {
+    error.to_string().contains("UNIQUE constraint failed")
+}
@@ -0,0 +1,231 @@
+use rocket::http::Status;
+use rocket::request::FromParam;
+use rocket::serde::{
+    json::{Error as JsonError, Json},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_json::{json, Value};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::{fmt, str::FromStr};
+
+use super::{SysResponse, WebResponse};
+
+/// A wallet, mint or other account address given as base58.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Base58Pubkey(pub Pubkey);
+
+/// A transaction signature given as base58.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Base58Signature(pub Signature);
+
+#[derive(Debug)]
+pub struct InvalidParam {
+    pub kind: &'static str,
+    pub value: String,
+}
+
+impl InvalidParam {
+    pub fn response(&self) -> WebResponse {
+        let data = json!({
+            "error": format!("Malformed base58 {}", self.kind),
+            "value": self.value,
+        });
+        let response = SysResponse { data };
+
+        (Status::UnprocessableEntity, Json(response))
+    }
+}
+
+/// A malformed base58 value in a JSON body, and the field that held it.
+#[derive(Debug)]
+pub struct InvalidField {
+    pub field: String,
+    pub param: InvalidParam,
+}
+
+impl InvalidField {
+    pub fn response(&self) -> WebResponse {
+        let data = json!({
+            "error": format!("Malformed base58 {}", self.param.kind),
+            "field": self.field,
+            "value": self.param.value,
+        });
+        let response = SysResponse { data };
+
+        (Status::UnprocessableEntity, Json(response))
+    }
+}
+
+/// Finds the field of a JSON object whose malformed base58 value `error` was
+/// raised for. The `Deserialize` impls below report the value, and serde adds
+/// where in the body it was.
+pub fn invalid_field(body: &[u8], error: &serde_json::Error) -> Option<InvalidField> {
+    let fields = match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(fields)) => fields,
+        _ => return None,
+    };
+    let message = error.to_string();
+    let position = format!(" at line {} column {}", error.line(), error.column());
+
+    fields.into_iter().find_map(|(field, value)| {
+        let value = value.as_str()?;
+
+        ["pubkey", "signature"]
+            .into_iter()
+            .map(|kind| InvalidParam {
+                kind,
+                value: value.to_string(),
+            })
+            .find(|param| message == format!("{}{}", param, position))
+            .map(|param| InvalidField { field, param })
+    })
+}
+
+/// Response for a JSON body Rocket could not read, with the offending field
+/// when it is a malformed base58 value.
+pub fn body_error(error: JsonError<'_>) -> WebResponse {
+    let (status, message) = match error {
+        JsonError::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            (Status::PayloadTooLarge, "Request body is too large")
+        }
+        JsonError::Io(_) => (Status::BadRequest, "Request body could not be read"),
+        JsonError::Parse(body, e) if e.is_data() => {
+            if let Some(field) = invalid_field(body.as_bytes(), &e) {
+                return field.response();
+            }
+
+            (
+                Status::UnprocessableEntity,
+                "Request body is missing or has invalid fields",
+            )
+        }
+        JsonError::Parse(_, _) => (Status::BadRequest, "Request body is not valid JSON"),
+    };
+    let data = json!({ "error": message });
+    let response = SysResponse { data };
+
+    (status, Json(response))
+}
+
+impl fmt::Display for InvalidParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed base58 {}: {}", self.kind, self.value)
+    }
+}
+
+impl FromStr for Base58Pubkey {
+    type Err = InvalidParam;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Pubkey::from_str(value)
+            .map(Base58Pubkey)
+            .map_err(|_| InvalidParam {
+                kind: "pubkey",
+                value: value.to_string(),
+            })
+    }
+}
+
+impl FromStr for Base58Signature {
+    type Err = InvalidParam;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Signature::from_str(value)
+            .map(Base58Signature)
+            .map_err(|_| InvalidParam {
+                kind: "signature",
+                value: value.to_string(),
+            })
+    }
+}
+
+impl fmt::Display for Base58Pubkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Base58Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<'a> FromParam<'a> for Base58Pubkey {
+    type Error = InvalidParam;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        param.parse()
+    }
+}
+
+impl<'a> FromParam<'a> for Base58Signature {
+    type Error = InvalidParam;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        param.parse()
+    }
+}
+
+impl<'de> Deserialize<'de> for Base58Pubkey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(rocket::serde::de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Base58Signature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(rocket::serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Base58Pubkey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Serialize for Base58Signature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Body {
+        account: Base58Pubkey,
+        tx_id: Option<Base58Signature>,
+    }
+
+    fn field_of(body: &str) -> Option<(String, &'static str, String)> {
+        let error = serde_json::from_str::<Body>(body).err().unwrap();
+
+        invalid_field(body.as_bytes(), &error)
+            .map(|field| (field.field, field.param.kind, field.param.value))
+    }
+
+    #[test]
+    fn finds_the_field_with_a_malformed_value() {
+        assert_eq!(
+            field_of(r#"{"account": "not-a-key", "tx_id": null}"#),
+            Some(("account".to_string(), "pubkey", "not-a-key".to_string()))
+        );
+        assert_eq!(
+            field_of(r#"{"account": "11111111111111111111111111111111", "tx_id": "0x1"}"#),
+            Some(("tx_id".to_string(), "signature", "0x1".to_string()))
+        );
+    }
+
+    #[test]
+    fn ignores_errors_that_are_not_base58() {
+        assert_eq!(field_of(r#"{"tx_id": null}"#), None);
+        assert_eq!(field_of(r#"{"account": 7}"#), None);
+    }
+}
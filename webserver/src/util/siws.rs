@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use rocket::serde::Deserialize;
+
+const HEADER_SUFFIX: &str = " wants you to sign in with your Solana account:";
+const VERSION: &str = "1";
+
+/// Server-side values every sign-in message has to be bound to.
+#[derive(Deserialize)]
+pub struct SiwsConfig {
+    pub domain: String,
+    pub uri: String,
+    pub statement: String,
+    pub chain_id: String,
+}
+
+/// Sign-In-With-Solana message, rendered as human-readable text for the
+/// wallet to display and sign.
+#[derive(Debug, PartialEq)]
+pub struct SiwsMessage {
+    pub domain: String,
+    pub address: String,
+    pub statement: String,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: String,
+    pub nonce: String,
+    pub issued_at: String,
+    pub expiration_time: String,
+}
+
+impl SiwsMessage {
+    pub fn new(
+        config: &SiwsConfig,
+        address: &str,
+        nonce: &str,
+        issued_at: NaiveDateTime,
+        expiration_time: NaiveDateTime,
+    ) -> Self {
+        SiwsMessage {
+            domain: config.domain.clone(),
+            address: address.to_string(),
+            statement: config.statement.clone(),
+            uri: config.uri.clone(),
+            version: VERSION.to_string(),
+            chain_id: config.chain_id.clone(),
+            nonce: nonce.to_string(),
+            issued_at: format_time(issued_at),
+            expiration_time: format_time(expiration_time),
+        }
+    }
+
+    pub fn to_message(&self) -> String {
+        format!(
+            "{}{}\n{}\n\n{}\n\nURI: {}\nVersion: {}\nChain ID: {}\nNonce: {}\nIssued At: {}\nExpiration Time: {}",
+            self.domain,
+            HEADER_SUFFIX,
+            self.address,
+            self.statement,
+            self.uri,
+            self.version,
+            self.chain_id,
+            self.nonce,
+            self.issued_at,
+            self.expiration_time,
+        )
+    }
+
+    pub fn parse(message: &str) -> Result<Self> {
+        let mut lines = message.split('\n');
+
+        let domain = match lines.next().and_then(|line| line.strip_suffix(HEADER_SUFFIX)) {
+            Some(domain) => domain.to_string(),
+            None => return Err(anyhow!("Sign-in message header is malformed")),
+        };
+        let address = next_line(&mut lines, "address")?;
+        expect_blank(&mut lines)?;
+        let statement = next_line(&mut lines, "statement")?;
+        expect_blank(&mut lines)?;
+
+        Ok(SiwsMessage {
+            domain,
+            address,
+            statement,
+            uri: next_field(&mut lines, "URI")?,
+            version: next_field(&mut lines, "Version")?,
+            chain_id: next_field(&mut lines, "Chain ID")?,
+            nonce: next_field(&mut lines, "Nonce")?,
+            issued_at: next_field(&mut lines, "Issued At")?,
+            expiration_time: next_field(&mut lines, "Expiration Time")?,
+        })
+    }
+
+    /// Checks that every field of a signed message matches what the server
+    /// issued for this login attempt.
+    pub fn validate(&self, expected: &SiwsMessage) -> Result<()> {
+        let fields = [
+            ("domain", &self.domain, &expected.domain),
+            ("address", &self.address, &expected.address),
+            ("statement", &self.statement, &expected.statement),
+            ("URI", &self.uri, &expected.uri),
+            ("version", &self.version, &expected.version),
+            ("chain ID", &self.chain_id, &expected.chain_id),
+            ("nonce", &self.nonce, &expected.nonce),
+            ("issued-at", &self.issued_at, &expected.issued_at),
+            ("expiration time", &self.expiration_time, &expected.expiration_time),
+        ];
+
+        for (name, received, wanted) in fields {
+            if received != wanted {
+                return Err(anyhow!("Sign-in message {} does not match", name));
+            }
+        }
+
+        let expiration = DateTime::parse_from_rfc3339(&self.expiration_time)?;
+        if expiration < Utc::now() {
+            return Err(anyhow!("Sign-in message has expired"));
+        }
+
+        Ok(())
+    }
+}
+
+fn format_time(time: NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(time, Utc).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn next_line<'a>(lines: &mut impl Iterator<Item = &'a str>, name: &str) -> Result<String> {
+    match lines.next() {
+        Some(line) if !line.is_empty() => Ok(line.to_string()),
+        _ => Err(anyhow!("Sign-in message is missing the {}", name)),
+    }
+}
+
+fn expect_blank<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Result<()> {
+    match lines.next() {
+        Some("") => Ok(()),
+        _ => Err(anyhow!("Sign-in message is malformed")),
+    }
+}
+
+fn next_field<'a>(lines: &mut impl Iterator<Item = &'a str>, name: &str) -> Result<String> {
+    let prefix = format!("{}: ", name);
+
+    match lines.next().and_then(|line| line.strip_prefix(prefix.as_str())) {
+        Some(value) => Ok(value.to_string()),
+        None => Err(anyhow!("Sign-in message is missing the {} field", name)),
+    }
+}
@@ -0,0 +1,326 @@
+use crate::handlers::payment::{
+    fetch_transaction, process_payment, transferred, ConfirmedPayment, Currency, PaymentError,
+    PAYMENT_MEMO_PREFIX,
+};
+use crate::rpc::{parse_commitment, SolanaRpc};
+use crate::Config;
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDateTime, Utc};
+use entity::payments::{self, Entity as Payments, PaymentStatus};
+use entity::watcher_cursors::{self, Entity as WatcherCursors};
+use entity::watcher_failures;
+use rocket::serde::Deserialize;
+use sea_orm::ActiveValue::NotSet;
+use sea_orm::{entity::*, query::*, DatabaseConnection};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature,
+    transaction::VersionedTransaction,
+};
+use spl_associated_token_account::get_associated_token_address;
+use std::{str::FromStr, time::Duration};
+
+/// `[default.watcher]` section of `Rocket.toml`.
+#[derive(Deserialize)]
+pub struct WatcherConfig {
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    pub page_size: usize,
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        WatcherConfig {
+            enabled: true,
+            interval_seconds: 15,
+            page_size: 100,
+        }
+    }
+}
+
+/// Polls the treasury for new transactions and confirms the pending payments
+/// they pay for, so a rank-up goes through even if the client never calls the
+/// payment hook.
+pub async fn run(db: DatabaseConnection, config: Config, rpc: SolanaRpc) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.watcher.interval_seconds));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = poll(&db, &config, &rpc).await {
+            log::warn!("Treasury watcher: {}", e);
+        }
+    }
+}
+
+async fn poll(db: &DatabaseConnection, config: &Config, rpc: &SolanaRpc) -> Result<()> {
+    let commitment = parse_commitment(&config.payment_commitment)?;
+
+    // -- Token transfers only touch the treasury's associated token account
+    let address = match config.currency.currency() {
+        Currency::Sol => config.treasury.0,
+        Currency::Token { mint, .. } => get_associated_token_address(&config.treasury.0, &mint),
+    };
+    let cursor_name = format!("treasury:{}", address);
+
+    let cursor = WatcherCursors::find()
+        .filter(watcher_cursors::Column::Name.eq(cursor_name.as_str()))
+        .one(db)
+        .await?;
+    let until = cursor
+        .as_ref()
+        .and_then(|cursor| Signature::from_str(&cursor.signature).ok());
+
+    let statuses =
+        fetch_signatures(rpc, &address, until, commitment, config.watcher.page_size).await?;
+
+    // -- Oldest first, moving the cursor after each one so a restart picks up
+    // -- exactly where the last run stopped
+    for status in statuses.iter().rev() {
+        let signature = match Signature::from_str(&status.signature) {
+            Ok(signature) => signature,
+            Err(_) => continue,
+        };
+
+        if status.err.is_none() {
+            match process_signature(db, config, rpc, commitment, &signature).await {
+                Ok(_) => (),
+                // -- The cursor stays before this transaction so the next poll
+                // -- tries it again
+                Err(Failure::Retry(reason)) => return Err(anyhow!("{}: {}", signature, reason)),
+                Err(Failure::Skip(reason)) => {
+                    log::warn!("Treasury watcher: skipping {}: {}", signature, reason);
+                    record_failure(db, &cursor_name, &signature, &reason).await?;
+                }
+            }
+        }
+
+        save_cursor(db, &cursor_name, &signature).await?;
+    }
+
+    Ok(())
+}
+
+/// Why a treasury transaction was not processed.
+enum Failure {
+    /// RPC or database trouble, which the next poll retries.
+    Retry(String),
+    /// The transaction can never confirm its payment, so it is recorded and
+    /// skipped.
+    Skip(String),
+}
+
+impl From<PaymentError> for Failure {
+    fn from(e: PaymentError) -> Self {
+        match e {
+            PaymentError::Unavailable(_) | PaymentError::Database(_) => {
+                Failure::Retry(e.to_string())
+            }
+            e => Failure::Skip(e.to_string()),
+        }
+    }
+}
+
+/// Signatures newer than `until`, newest first. Without a cursor only the
+/// latest page is returned so a fresh install does not replay old history.
+async fn fetch_signatures(
+    rpc: &SolanaRpc,
+    address: &Pubkey,
+    until: Option<Signature>,
+    commitment: CommitmentConfig,
+    page_size: usize,
+) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+    let address = *address;
+    let mut statuses = Vec::new();
+    let mut before = None;
+
+    loop {
+        let page = rpc.call(move |client| {
+            let config = GetConfirmedSignaturesForAddress2Config {
+                before,
+                until,
+                limit: Some(page_size),
+                commitment: Some(commitment),
+            };
+            client.get_signatures_for_address_with_config(&address, config)
+        });
+
+        let page = match page.await {
+            Ok(page) => page,
+            Err(e) => return Err(anyhow!("Failed to fetch treasury signatures: {}", e)),
+        };
+
+        let full = page.len() == page_size;
+        before = page
+            .last()
+            .and_then(|status| Signature::from_str(&status.signature).ok());
+        statuses.extend(page);
+
+        if !full || until.is_none() || before.is_none() {
+            return Ok(statuses);
+        }
+    }
+}
+
+async fn process_signature(
+    db: &DatabaseConnection,
+    config: &Config,
+    rpc: &SolanaRpc,
+    commitment: CommitmentConfig,
+    signature: &Signature,
+) -> Result<(), Failure> {
+    let (transaction, confirmed) = fetch_transaction(rpc, signature, commitment).await?;
+
+    let payment = match match_payment(db, config, &transaction, &confirmed).await {
+        Ok(Some(payment)) => payment,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(Failure::Retry(e.to_string())),
+    };
+
+    match process_payment(db, config, rpc, payment.id, signature).await {
+        Ok(processed) => log::info!(
+            "Treasury watcher: confirmed payment {} with {} (rank-up job {})",
+            payment.id,
+            signature,
+            processed.job_id
+        ),
+        Err(PaymentError::AlreadyConfirmed) | Err(PaymentError::SignatureUsed) => (),
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(())
+}
+
+/// Finds the pending payment a transaction pays for, by Solana Pay reference
+/// first, then by payment memo, then by fee payer and amount.
+async fn match_payment(
+    db: &DatabaseConnection,
+    config: &Config,
+    transaction: &VersionedTransaction,
+    confirmed: &ConfirmedPayment,
+) -> Result<Option<payments::Model>> {
+    let keys = transaction.message.static_account_keys();
+    let pending = Payments::find().filter(payments::Column::Status.eq(PaymentStatus::Pending));
+
+    let references: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+    let by_reference = pending
+        .clone()
+        .filter(payments::Column::Reference.is_in(references))
+        .one(db)
+        .await?;
+
+    if by_reference.is_some() {
+        return Ok(by_reference);
+    }
+
+    for memo in memos(transaction) {
+        let payment_id = memo
+            .strip_prefix(PAYMENT_MEMO_PREFIX)
+            .and_then(|id| id.trim().parse::<i32>().ok());
+
+        if let Some(payment_id) = payment_id {
+            let by_memo = pending
+                .clone()
+                .filter(payments::Column::Id.eq(payment_id))
+                .one(db)
+                .await?;
+
+            if by_memo.is_some() {
+                return Ok(by_memo);
+            }
+        }
+    }
+
+    // -- A plain transfer is only matched when exactly one pending payment of
+    // -- its fee payer, opened before it landed, asks for exactly the amount
+    // -- sent, so a payment is never guessed
+    let payer = match keys.first() {
+        Some(payer) => payer,
+        None => return Ok(None),
+    };
+    let landed_at = confirmed
+        .block_time
+        .map(|time| NaiveDateTime::from_timestamp(time, 0));
+
+    let by_payer = pending
+        .filter(payments::Column::Account.eq(payer.to_string()))
+        .all(db)
+        .await?;
+
+    let mut by_amount = by_payer.into_iter().filter(|payment| {
+        let currency = match Currency::parse(&payment.currency, payment.decimals) {
+            Ok(currency) => currency,
+            Err(_) => return false,
+        };
+        let paid = transferred(transaction, payer, &config.treasury.0, &currency);
+        let opened = landed_at.map_or(true, |landed_at| payment.created_at <= landed_at);
+
+        opened && paid > 0 && paid == payment.base_amount.max(0) as u64
+    });
+
+    match (by_amount.next(), by_amount.next()) {
+        (Some(payment), None) => Ok(Some(payment)),
+        _ => Ok(None),
+    }
+}
+
+fn memos(transaction: &VersionedTransaction) -> Vec<String> {
+    let keys = transaction.message.static_account_keys();
+
+    transaction
+        .message
+        .instructions()
+        .iter()
+        .filter(|instruction| {
+            let program = keys.get(instruction.program_id_index as usize);
+            program == Some(&spl_memo::id()) || program == Some(&spl_memo::v1::id())
+        })
+        .filter_map(|instruction| String::from_utf8(instruction.data.clone()).ok())
+        .collect()
+}
+
+async fn record_failure(
+    db: &DatabaseConnection,
+    cursor: &str,
+    signature: &Signature,
+    error: &str,
+) -> Result<()> {
+    let failure = watcher_failures::ActiveModel {
+        id: NotSet,
+        cursor: Set(cursor.to_string()),
+        signature: Set(signature.to_string()),
+        error: Set(error.to_string()),
+        created_at: Set(Utc::now().naive_utc()),
+    };
+    failure.insert(db).await?;
+
+    Ok(())
+}
+
+async fn save_cursor(db: &DatabaseConnection, name: &str, signature: &Signature) -> Result<()> {
+    let found = WatcherCursors::find()
+        .filter(watcher_cursors::Column::Name.eq(name))
+        .one(db)
+        .await?;
+
+    match found {
+        Some(cursor) => {
+            let mut cursor: watcher_cursors::ActiveModel = cursor.into();
+            cursor.signature = Set(signature.to_string());
+            cursor.updated_at = Set(Utc::now().naive_utc());
+            cursor.update(db).await?;
+        }
+        None => {
+            let cursor = watcher_cursors::ActiveModel {
+                id: NotSet,
+                name: Set(name.to_string()),
+                signature: Set(signature.to_string()),
+                updated_at: Set(Utc::now().naive_utc()),
+            };
+            cursor.insert(db).await?;
+        }
+    }
+
+    Ok(())
+}